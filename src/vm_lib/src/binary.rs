@@ -0,0 +1,350 @@
+use std::{
+    fmt::{self, Display},
+    io::{Read, Write},
+};
+
+use crate::{ByteCode, Executable, NativeType};
+
+/// File signature: "SVM" followed by a 0x01 byte.
+pub const MAGIC: [u8; 4] = *b"SVM\x01";
+/// Bumped on every incompatible change to the encoding, files of any other version
+/// are rejected rather than misread.
+pub const FORMAT_VERSION: u16 = 2;
+/// Deepest nesting of values accepted when decoding, so a hostile file cannot
+/// overflow the stack.
+pub const MAX_DEPTH: usize = 128;
+
+const FLAG_DEBUG: u16 = 1;
+
+const SECTION_CODE: u8 = 1;
+const SECTION_CONSTANTS: u8 = 2;
+const SECTION_DEBUG: u8 = 3;
+
+// ------------------------
+// MARK: TYPES
+//------------------------
+
+#[derive(Debug)]
+pub enum FormatError {
+    Io(std::io::Error),
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    TrailingBytes,
+    MissingSection(&'static str),
+    InvalidTag { kind: &'static str, tag: u8 },
+    InvalidUtf8,
+    Unsupported(&'static str),
+    // Labels must be resolved by `ProgramCode::compile` before writing
+    UnresolvedLabel(String),
+    TooDeep,
+}
+
+/// Optional metadata kept alongside the code, e.g. label names for disassembly.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DebugInfo {
+    pub symbols: Vec<(usize, String)>,
+}
+
+pub struct Writer {
+    bytes: Vec<u8>,
+    // First value that could not be encoded, reported once writing ends
+    error: Option<FormatError>,
+}
+
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+// ------------------------
+// MARK: TRAITS
+//------------------------
+
+/// Binary encoding of instructions and values inside a bytecode file.
+pub trait Serializable
+where
+    Self: Sized,
+{
+    fn encode(&self, out: &mut Writer);
+
+    fn decode(input: &mut Reader) -> Result<Self, FormatError>;
+}
+
+// ------------------------
+// MARK: IMPLEMENTS
+//------------------------
+
+impl<Op, D> ByteCode<Op, D>
+where
+    Op: Executable<D> + Serializable,
+    D: NativeType + Serializable,
+{
+    /// Writes the header followed by the code, constants and (if present) debug sections.
+    pub fn write_to(&self, writer: &mut impl Write) -> Result<(), FormatError> {
+        let mut out = Writer::new();
+        out.bytes.extend_from_slice(&MAGIC);
+        out.u16(FORMAT_VERSION);
+        let flags = match self.get_debug() {
+            Some(_) => FLAG_DEBUG,
+            None => 0,
+        };
+        out.u16(flags);
+
+        out.section(SECTION_CODE, |out| out.items(self.get()));
+        out.section(SECTION_CONSTANTS, |out| out.items(self.get_constants()));
+        if let Some(debug) = self.get_debug() {
+            out.section(SECTION_DEBUG, |out| debug.encode(out));
+        }
+        if let Some(error) = out.error {
+            return Err(error);
+        }
+
+        writer.write_all(&out.bytes).map_err(FormatError::Io)
+    }
+
+    pub fn read_from(reader: &mut impl Read) -> Result<Self, FormatError> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes).map_err(FormatError::Io)?;
+        let mut input = Reader::new(&bytes);
+
+        if input.take(MAGIC.len())? != MAGIC {
+            return Err(FormatError::BadMagic);
+        }
+        let version = input.u16()?;
+        if version != FORMAT_VERSION {
+            return Err(FormatError::UnsupportedVersion(version));
+        }
+        let flags = input.u16()?;
+
+        let mut instructions = None;
+        let mut constants = None;
+        let mut debug = None;
+        while !input.is_empty() {
+            let tag = input.u8()?;
+            let len = input.u32()? as usize;
+            let mut section = Reader::new(input.take(len)?);
+            match tag {
+                SECTION_CODE => instructions = Some(section.items::<Op>()?),
+                SECTION_CONSTANTS => constants = Some(section.items::<D>()?),
+                SECTION_DEBUG => debug = Some(DebugInfo::decode(&mut section)?),
+                _ => {
+                    let kind = "section";
+                    return Err(FormatError::InvalidTag { kind, tag });
+                }
+            }
+            if !section.is_empty() {
+                return Err(FormatError::TrailingBytes);
+            }
+        }
+
+        let instructions = instructions.ok_or(FormatError::MissingSection("code"))?;
+        let constants = constants.ok_or(FormatError::MissingSection("constants"))?;
+        if flags & FLAG_DEBUG != 0 && debug.is_none() {
+            return Err(FormatError::MissingSection("debug"));
+        }
+
+        let bytecode = ByteCode::new(instructions.into(), constants.into());
+        Ok(match debug {
+            Some(debug) => bytecode.with_debug(debug),
+            None => bytecode,
+        })
+    }
+}
+
+impl Serializable for DebugInfo {
+    fn encode(&self, out: &mut Writer) {
+        out.u32(self.symbols.len() as u32);
+        for (index, name) in &self.symbols {
+            out.usize(*index);
+            out.str(name);
+        }
+    }
+
+    fn decode(input: &mut Reader) -> Result<Self, FormatError> {
+        let len = input.u32()?;
+        let symbols = (0..len)
+            .map(|_| Ok((input.usize()?, input.string()?)))
+            .collect::<Result<_, FormatError>>()?;
+        Ok(DebugInfo { symbols })
+    }
+}
+
+impl Default for Writer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Writer {
+    pub const fn new() -> Self {
+        Writer {
+            bytes: vec![],
+            error: None,
+        }
+    }
+
+    pub fn into_bytes(self) -> Result<Vec<u8>, FormatError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.bytes),
+        }
+    }
+
+    /// Marks the output as invalid, keeping the first error.
+    pub fn fail(&mut self, error: FormatError) {
+        self.error.get_or_insert(error);
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn usize(&mut self, value: usize) {
+        self.u64(value as u64);
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.u32(value.len() as u32);
+        self.bytes.extend_from_slice(value);
+    }
+
+    pub fn str(&mut self, value: &str) {
+        self.bytes(value.as_bytes());
+    }
+
+    pub fn items<T: Serializable>(&mut self, items: &[T]) {
+        self.u32(items.len() as u32);
+        for item in items {
+            item.encode(self);
+        }
+    }
+
+    /// Writes a tagged section prefixed with its length in bytes.
+    fn section(&mut self, tag: u8, body: impl FnOnce(&mut Writer)) {
+        let mut section = Writer::new();
+        body(&mut section);
+        if let Some(error) = section.error {
+            self.fail(error);
+        }
+
+        self.u8(tag);
+        self.bytes(&section.bytes);
+    }
+}
+
+impl<'a> Reader<'a> {
+    pub const fn new(bytes: &'a [u8]) -> Self {
+        Reader {
+            bytes,
+            pos: 0,
+            depth: 0,
+        }
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8], FormatError> {
+        let end = self.pos.checked_add(len).ok_or(FormatError::Truncated)?;
+        let bytes = self
+            .bytes
+            .get(self.pos..end)
+            .ok_or(FormatError::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], FormatError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, FormatError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, FormatError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, FormatError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, FormatError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub fn usize(&mut self) -> Result<usize, FormatError> {
+        usize::try_from(self.u64()?).map_err(|_| FormatError::Unsupported("pointer width"))
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], FormatError> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    pub fn string(&mut self) -> Result<String, FormatError> {
+        let bytes = self.bytes()?.to_vec();
+        String::from_utf8(bytes).map_err(|_| FormatError::InvalidUtf8)
+    }
+
+    pub fn items<T: Serializable>(&mut self) -> Result<Vec<T>, FormatError> {
+        let len = self.u32()?;
+        (0..len).map(|_| T::decode(self)).collect()
+    }
+
+    /// Decodes a value nested inside another one, failing past `MAX_DEPTH` levels.
+    pub fn nested<T>(
+        &mut self,
+        decode: impl FnOnce(&mut Self) -> Result<T, FormatError>,
+    ) -> Result<T, FormatError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(FormatError::TooDeep);
+        }
+        self.depth += 1;
+        let result = decode(self);
+        self.depth -= 1;
+        result
+    }
+}
+
+impl Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::Io(err) => write!(f, "i/o error: {}", err),
+            FormatError::BadMagic => f.write_str("not a bytecode file"),
+            FormatError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported format version {} (expected {})",
+                version, FORMAT_VERSION
+            ),
+            FormatError::Truncated => f.write_str("truncated bytecode file"),
+            FormatError::TrailingBytes => f.write_str("unexpected bytes at the end of a section"),
+            FormatError::MissingSection(name) => write!(f, "missing {} section", name),
+            FormatError::InvalidTag { kind, tag } => write!(f, "invalid {} tag {}", kind, tag),
+            FormatError::InvalidUtf8 => f.write_str("invalid utf-8 string"),
+            FormatError::Unsupported(what) => write!(f, "unsupported {}", what),
+            FormatError::UnresolvedLabel(label) => write!(f, "unresolved label `{}`", label),
+            FormatError::TooDeep => write!(f, "values nested deeper than {} levels", MAX_DEPTH),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
@@ -1,20 +1,47 @@
-use log::debug;
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    rc::Rc,
+};
 
-use crate::{Executable, NativeType};
+use crate::{DebugInfo, Diagnostic, Executable, NativeType, Serializable, Writer};
 
+pub type Labels = HashMap<String, usize>;
+
+#[derive(Debug)]
 pub struct ProgramCode<Op: Executable<D>, D: NativeType> {
     instructions: Vec<Op>,
     constants: Vec<D>,
+    labels: Vec<(String, usize)>,
 }
 
+#[derive(Debug)]
 pub struct ByteCode<Op: Executable<D>, D: NativeType> {
     instructions: Box<[Op]>,
-    constants: Box<[D]>,
+    constants: Rc<[D]>,
+    debug: Option<DebugInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompileError {
+    UndefinedLabel { label: String, ipointer: usize },
+    DuplicateLabel { label: String, ipointer: usize },
+    MisplacedLabel { label: String, ipointer: usize },
+    // Rejected by `ByteCode::verify`
+    Verification(Vec<Diagnostic>),
+}
+
+/// Constant pool being built by `compile`, sharing one slot between identical values.
+#[derive(Debug)]
+pub struct ConstantPool<D: NativeType> {
+    constants: Vec<D>,
+    // Slot of each distinct encoding, filled on the first `intern`
+    index: HashMap<Vec<u8>, usize>,
 }
 
-/// ------------------------
-/// MARK: IMPLEMENTS
-/// ------------------------
+// ------------------------
+// MARK: IMPLEMENTS
+//------------------------
 
 impl<Op, D> ProgramCode<Op, D>
 where
@@ -25,14 +52,58 @@ where
         ProgramCode {
             instructions,
             constants,
+            labels: vec![],
         }
     }
 
-    pub fn compile(&self) -> ByteCode<Op, D> {
-        ByteCode {
-            instructions: self.instructions.clone().into_boxed_slice(),
-            constants: self.constants.clone().into_boxed_slice(),
+    pub fn push(&mut self, op: Op) -> &mut Self {
+        self.instructions.push(op);
+        self
+    }
+
+    /// Names the position of the next pushed instruction.
+    pub fn label(&mut self, name: impl Into<String>) -> &mut Self {
+        self.define_label(name, self.instructions.len())
+    }
+
+    pub fn define_label(&mut self, name: impl Into<String>, ipointer: usize) -> &mut Self {
+        self.labels.push((name.into(), ipointer));
+        self
+    }
+
+    /// Builds the bytecode: label references become jump offsets relative to the
+    /// referencing instruction, and inline constants are hoisted into the pool.
+    pub fn compile(&self) -> Result<ByteCode<Op, D>, CompileError> {
+        let mut labels = Labels::new();
+        for (label, ipointer) in &self.labels {
+            if labels.insert(label.clone(), *ipointer).is_some() {
+                let (label, ipointer) = (label.clone(), *ipointer);
+                return Err(CompileError::DuplicateLabel { label, ipointer });
+            }
+        }
+
+        let mut pool = ConstantPool::new(self.constants.clone());
+        let mut instructions = self.instructions.clone();
+        for (ipointer, op) in instructions.iter_mut().enumerate() {
+            op.resolve_labels(ipointer, &labels)?;
+            op.hoist_constants(&mut pool);
         }
+
+        let bytecode = ByteCode::new(
+            instructions.into_boxed_slice(),
+            pool.constants.into_boxed_slice(),
+        );
+        if self.labels.is_empty() {
+            return Ok(bytecode);
+        }
+
+        let mut symbols = self.labels.clone();
+        symbols.sort_by_key(|(_, ipointer)| *ipointer);
+        let symbols = symbols
+            .into_iter()
+            .map(|(label, ipointer)| (ipointer, label))
+            .collect();
+        Ok(bytecode.with_debug(DebugInfo { symbols }))
     }
 }
 
@@ -44,10 +115,16 @@ where
     pub fn new(instructions: Box<[Op]>, constants: Box<[D]>) -> Self {
         ByteCode {
             instructions,
-            constants,
+            constants: constants.into(),
+            debug: None,
         }
     }
 
+    pub fn with_debug(mut self, debug: DebugInfo) -> Self {
+        self.debug = Some(debug);
+        self
+    }
+
     pub const fn get(&self) -> &[Op] {
         &self.instructions
     }
@@ -55,7 +132,96 @@ where
         //debug!("IP {:?}  ", ipointer);
         &self.instructions[ipointer]
     }
-    pub const fn get_constants(&self) -> &[D] {
+    pub fn try_get_at(&self, ipointer: usize) -> Option<&Op> {
+        self.instructions.get(ipointer)
+    }
+    pub fn get_constants(&self) -> &[D] {
+        &self.constants
+    }
+    pub fn share_constants(&self) -> Rc<[D]> {
+        self.constants.clone()
+    }
+    pub const fn get_debug(&self) -> Option<&DebugInfo> {
+        self.debug.as_ref()
+    }
+}
+
+impl<D: NativeType> ConstantPool<D> {
+    pub fn new(constants: Vec<D>) -> Self {
+        ConstantPool {
+            constants,
+            index: HashMap::new(),
+        }
+    }
+
+    /// Returns the index of `value` in the pool, appending it if it isn't there yet.
+    /// Values are matched by their encoding, not `==`, so e.g. `-0.0` keeps its own slot
+    /// even when it compares equal to `0.0`.
+    pub fn intern(&mut self, value: D) -> usize
+    where
+        D: Serializable,
+    {
+        if self.index.is_empty() {
+            // Reversed so repeated constants map to their first slot
+            for (index, constant) in self.constants.iter().enumerate().rev() {
+                if let Some(key) = Self::key(constant) {
+                    self.index.insert(key, index);
+                }
+            }
+        }
+        let key = Self::key(&value);
+        if let Some(index) = key.as_ref().and_then(|key| self.index.get(key)) {
+            return *index;
+        }
+
+        let index = self.constants.len();
+        if let Some(key) = key {
+            self.index.insert(key, index);
+        }
+        self.constants.push(value);
+        index
+    }
+
+    /// Values that cannot be encoded are never shared.
+    fn key(value: &D) -> Option<Vec<u8>>
+    where
+        D: Serializable,
+    {
+        let mut out = Writer::new();
+        value.encode(&mut out);
+        out.into_bytes().ok()
+    }
+
+    pub fn get(&self) -> &[D] {
         &self.constants
     }
 }
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::UndefinedLabel { label, ipointer } => {
+                write!(f, "undefined label `{}` at IP {}", label, ipointer)
+            }
+            CompileError::DuplicateLabel { label, ipointer } => {
+                write!(f, "duplicate label `{}` at IP {}", label, ipointer)
+            }
+            CompileError::MisplacedLabel { label, ipointer } => {
+                write!(
+                    f,
+                    "label `{}` used outside a jump target at IP {}",
+                    label, ipointer
+                )
+            }
+            CompileError::Verification(diagnostics) => {
+                f.write_str("bytecode failed verification:")?;
+                for diagnostic in diagnostics {
+                    write!(f, "\n  {}", diagnostic)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
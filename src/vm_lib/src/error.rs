@@ -0,0 +1,152 @@
+use std::fmt::{self, Debug, Display};
+
+use crate::NativeType;
+
+// ------------------------
+// MARK: TYPES
+//------------------------
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+    TypeMismatch,
+    InvalidJump,
+    InvalidCondition,
+    InvalidTarget,
+    InvalidLoad,
+    DivisionByZero,
+    // Under `OverflowPolicy::Checked`
+    IntegerOverflow,
+    // Access to a slot holding no value, with the depth of the stack at the time
+    StackUnderflow { depth: usize, capacity: usize },
+    // Push past the capacity, with the depth of the stack at the time
+    StackOverflow { depth: usize, capacity: usize },
+    ReturnWithoutCall,
+    ConstantOutOfBounds,
+    UnresolvedLabel,
+    InstructionOutOfBounds,
+    // Heap handle to a freed or never allocated cell
+    DanglingPointer,
+    IndexOutOfBounds,
+    KeyNotFound,
+    // Collection with a different number of items than expected
+    LengthMismatch,
+    // No host function registered under the called name
+    UnknownFunction,
+    // Reported by a host function
+    NativeFailed(String),
+    // Error of the input source, not the end of input
+    InputFailed(String),
+    // String that does not spell a value of the requested type
+    ParseError,
+}
+
+/// Error raised by a single instruction, before the VM knows where it happened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecError<D: NativeType> {
+    pub kind: ErrorKind,
+    pub operands: Vec<D>,
+}
+
+/// Error surfaced by a process, located at the instruction that raised it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmError<D: NativeType> {
+    pub kind: ErrorKind,
+    pub pid: usize,
+    pub ipointer: usize,
+    pub instruction: Option<String>,
+    pub operands: Vec<D>,
+}
+
+// ------------------------
+// MARK: IMPLEMENTS
+//------------------------
+
+impl<D: NativeType> ExecError<D> {
+    pub const fn new(kind: ErrorKind, operands: Vec<D>) -> Self {
+        ExecError { kind, operands }
+    }
+}
+
+impl<D: NativeType> From<ErrorKind> for ExecError<D> {
+    fn from(kind: ErrorKind) -> Self {
+        ExecError::new(kind, vec![])
+    }
+}
+
+impl<D: NativeType> VmError<D> {
+    pub fn new(error: ExecError<D>, pid: usize, ipointer: usize, instruction: &impl Debug) -> Self {
+        VmError {
+            kind: error.kind,
+            pid,
+            ipointer,
+            instruction: Some(format!("{:?}", instruction)),
+            operands: error.operands,
+        }
+    }
+
+    pub const fn out_of_bounds(pid: usize, ipointer: usize) -> Self {
+        VmError {
+            kind: ErrorKind::InstructionOutOfBounds,
+            pid,
+            ipointer,
+            instruction: None,
+            operands: vec![],
+        }
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            ErrorKind::TypeMismatch => "type mismatch",
+            ErrorKind::InvalidJump => "jump offset must be an integer",
+            ErrorKind::InvalidCondition => "jump condition must be a boolean",
+            ErrorKind::InvalidTarget => "cannot copy to a constant",
+            ErrorKind::InvalidLoad => "cannot load from a non pointer value",
+            ErrorKind::DivisionByZero => "division by zero",
+            ErrorKind::IntegerOverflow => "integer overflow",
+            ErrorKind::StackUnderflow { depth, capacity } => {
+                return write!(f, "stack underflow at depth {} of {}", depth, capacity);
+            }
+            ErrorKind::StackOverflow { depth, capacity } => {
+                return write!(f, "stack overflow at depth {} of {}", depth, capacity);
+            }
+            ErrorKind::ReturnWithoutCall => "return without a matching call",
+            ErrorKind::ConstantOutOfBounds => "constant index out of bounds",
+            ErrorKind::UnresolvedLabel => "label was not resolved at compile time",
+            ErrorKind::InstructionOutOfBounds => "instruction pointer out of bounds",
+            ErrorKind::DanglingPointer => "pointer to a freed heap cell",
+            ErrorKind::IndexOutOfBounds => "index out of bounds",
+            ErrorKind::KeyNotFound => "key not found",
+            ErrorKind::LengthMismatch => "unexpected number of items",
+            ErrorKind::UnknownFunction => "no native function with that name",
+            ErrorKind::NativeFailed(message) => {
+                return write!(f, "native function failed: {}", message);
+            }
+            ErrorKind::InputFailed(message) => {
+                return write!(f, "reading input failed: {}", message);
+            }
+            ErrorKind::ParseError => "cannot parse value",
+        };
+        f.write_str(message)
+    }
+}
+
+impl<D: NativeType> Display for VmError<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[PID {}] {} at IP {}",
+            self.pid, self.kind, self.ipointer
+        )?;
+        if let Some(instruction) = &self.instruction {
+            write!(f, " ({})", instruction)?;
+        }
+        if !self.operands.is_empty() {
+            write!(f, " with operands {:?}", self.operands)?;
+        }
+        Ok(())
+    }
+}
+
+impl<D: NativeType> std::error::Error for VmError<D> {}
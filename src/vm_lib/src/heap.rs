@@ -0,0 +1,209 @@
+use std::time::{Duration, Instant};
+
+use log::{debug, info};
+
+use crate::{ErrorKind, NativeType};
+
+// ------------------------
+// MARK: TYPES
+//------------------------
+
+/// Reference to a heap cell, packed into a `u64` as `generation << 32 | index`
+/// on every platform. Generations start at 1, so no handle is ever 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle {
+    pub index: u32,
+    pub generation: u32,
+}
+
+#[derive(Debug, Clone)]
+struct Cell<D> {
+    generation: u32,
+    value: Option<D>,
+}
+
+/// When the scheduler runs the collector: once `threshold` cells are live, after which
+/// the next threshold is `growth` times the cells that survived (never below `threshold`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GcConfig {
+    pub threshold: usize,
+    pub growth: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GcStats {
+    pub collections: usize,
+    pub cells_reclaimed: usize,
+    // Sum of `NativeType::size` of the reclaimed values
+    pub bytes_reclaimed: usize,
+    pub last_pause: Duration,
+    pub total_pause: Duration,
+}
+
+/// Cells shared by every process. Freed slots are reused with a bumped generation,
+/// so stale handles are reported instead of reading whatever lives there now.
+#[derive(Debug, Clone, Default)]
+pub struct Heap<D: NativeType> {
+    cells: Vec<Cell<D>>,
+    free: Vec<u32>,
+    stats: GcStats,
+}
+
+// ------------------------
+// MARK: IMPLEMENTS
+//------------------------
+
+impl Handle {
+    pub const fn to_bits(self) -> u64 {
+        ((self.generation as u64) << 32) | self.index as u64
+    }
+
+    pub const fn from_bits(bits: u64) -> Self {
+        Handle {
+            index: bits as u32,
+            generation: (bits >> 32) as u32,
+        }
+    }
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        GcConfig {
+            threshold: 1024,
+            growth: 2,
+        }
+    }
+}
+
+impl<D: NativeType> Heap<D> {
+    pub const fn new() -> Self {
+        Heap {
+            cells: vec![],
+            free: vec![],
+            stats: GcStats {
+                collections: 0,
+                cells_reclaimed: 0,
+                bytes_reclaimed: 0,
+                last_pause: Duration::ZERO,
+                total_pause: Duration::ZERO,
+            },
+        }
+    }
+
+    pub fn alloc(&mut self, value: D) -> Handle {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.cells.push(Cell {
+                    generation: 0,
+                    value: None,
+                });
+                (self.cells.len() - 1) as u32
+            }
+        };
+
+        let cell = &mut self.cells[index as usize];
+        cell.generation = cell.generation.wrapping_add(1).max(1);
+        cell.value = Some(value);
+
+        debug!("\t HEAP ALLOC: {}", index);
+        Handle {
+            index,
+            generation: cell.generation,
+        }
+    }
+
+    /// Releases the cell, returning the value it held.
+    pub fn free(&mut self, handle: Handle) -> Result<D, ErrorKind> {
+        let cell = self.cell_mut(handle)?;
+        let value = cell.value.take().ok_or(ErrorKind::DanglingPointer)?;
+        self.free.push(handle.index);
+
+        debug!("\t HEAP FREE: {}", handle.index);
+        Ok(value)
+    }
+
+    pub fn get(&self, handle: Handle) -> Result<&D, ErrorKind> {
+        self.cells
+            .get(handle.index as usize)
+            .filter(|cell| cell.generation == handle.generation)
+            .and_then(|cell| cell.value.as_ref())
+            .ok_or(ErrorKind::DanglingPointer)
+    }
+
+    pub fn set(&mut self, handle: Handle, value: D) -> Result<(), ErrorKind> {
+        let cell = self.cell_mut(handle)?;
+        match &mut cell.value {
+            Some(slot) => *slot = value,
+            None => return Err(ErrorKind::DanglingPointer),
+        }
+        Ok(())
+    }
+
+    /// Number of cells currently allocated.
+    pub fn len(&self) -> usize {
+        self.cells.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub const fn stats(&self) -> &GcStats {
+        &self.stats
+    }
+
+    /// Mark and sweep: frees every cell not reachable from `roots`, following the
+    /// pointers reported by `NativeType::trace`. Returns the number of cells freed.
+    pub fn collect<'a>(&mut self, roots: impl IntoIterator<Item = &'a D>) -> usize
+    where
+        D: 'a,
+    {
+        let started = Instant::now();
+
+        let mut marked = vec![false; self.cells.len()];
+        let mut pending = vec![];
+        let mut mark = |bits: u64| pending.push(Handle::from_bits(bits));
+        for root in roots {
+            root.trace(&mut mark);
+        }
+        while let Some(handle) = pending.pop() {
+            let Ok(value) = self.get(handle) else {
+                continue;
+            };
+            if !std::mem::replace(&mut marked[handle.index as usize], true) {
+                value.trace(&mut |bits| pending.push(Handle::from_bits(bits)));
+            }
+        }
+
+        let mut reclaimed = 0;
+        let mut bytes = 0;
+        for (index, cell) in self.cells.iter_mut().enumerate() {
+            if marked[index] {
+                continue;
+            }
+            if let Some(value) = cell.value.take() {
+                self.free.push(index as u32);
+                reclaimed += 1;
+                bytes += value.size();
+            }
+        }
+
+        let pause = started.elapsed();
+        self.stats.collections += 1;
+        self.stats.cells_reclaimed += reclaimed;
+        self.stats.bytes_reclaimed += bytes;
+        self.stats.last_pause = pause;
+        self.stats.total_pause += pause;
+
+        info!("GC: reclaimed {} cells in {:?}", reclaimed, pause);
+        reclaimed
+    }
+
+    fn cell_mut(&mut self, handle: Handle) -> Result<&mut Cell<D>, ErrorKind> {
+        self.cells
+            .get_mut(handle.index as usize)
+            .filter(|cell| cell.generation == handle.generation)
+            .ok_or(ErrorKind::DanglingPointer)
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::{self, BufRead, BufReader, Cursor, Read},
+    path::Path,
+    rc::Rc,
+    sync::{
+        Mutex, MutexGuard, OnceLock, PoisonError,
+        mpsc::{self, Receiver, Sender, TryRecvError},
+    },
+    thread,
+};
+
+// ------------------------
+// MARK: TYPES
+//------------------------
+
+/// Source shared by the processes reading from it.
+pub type SharedInput = Rc<RefCell<dyn Input>>;
+
+/// Reads from the standard input without waiting for it: a background thread reads it
+/// while the processes waiting on it are blocked.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stdin;
+
+/// Standard input read so far, shared by every `Stdin`.
+#[derive(Debug)]
+struct StdinBuffer {
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    buffer: Vec<u8>,
+    ended: bool,
+}
+
+/// Input known in advance, e.g. to script a program in tests.
+#[derive(Debug, Clone, Default)]
+pub struct InputScript {
+    data: Cursor<Vec<u8>>,
+}
+
+#[derive(Debug)]
+pub struct InputFile {
+    reader: BufReader<File>,
+}
+
+// ------------------------
+// MARK: TRAITS
+//------------------------
+
+/// Where the input instructions of a process read from. `None` means the end of input.
+///
+/// Sources that cannot wait return `io::ErrorKind::WouldBlock` while no data is ready, the
+/// reading process then blocks and retries on its next turn.
+pub trait Input {
+    /// Next line without its line ending.
+    fn read_line(&mut self) -> io::Result<Option<String>>;
+
+    /// Up to `count` bytes, fewer only when the input ends first.
+    fn read_bytes(&mut self, count: usize) -> io::Result<Option<Vec<u8>>>;
+}
+
+// ------------------------
+// MARK: IMPLEMENTS
+//------------------------
+
+impl Stdin {
+    pub fn shared() -> SharedInput {
+        Rc::new(RefCell::new(Stdin))
+    }
+}
+
+impl Input for Stdin {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut stdin = StdinBuffer::get();
+        stdin.fill()?;
+
+        let end = match stdin.buffer.iter().position(|byte| *byte == b'\n') {
+            Some(newline) => newline + 1,
+            None if stdin.ended => stdin.buffer.len(),
+            None => return Err(io::ErrorKind::WouldBlock.into()),
+        };
+        let line: Vec<u8> = stdin.buffer.drain(..end).collect();
+        read_line(&mut line.as_slice())
+    }
+
+    fn read_bytes(&mut self, count: usize) -> io::Result<Option<Vec<u8>>> {
+        let mut stdin = StdinBuffer::get();
+        stdin.fill()?;
+
+        if stdin.buffer.len() < count && !stdin.ended {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let end = count.min(stdin.buffer.len());
+        let bytes: Vec<u8> = stdin.buffer.drain(..end).collect();
+        read_bytes(&mut bytes.as_slice(), count)
+    }
+}
+
+impl StdinBuffer {
+    /// Starts the reader thread on first use.
+    fn get() -> MutexGuard<'static, StdinBuffer> {
+        static STDIN: OnceLock<Mutex<StdinBuffer>> = OnceLock::new();
+
+        let stdin = STDIN.get_or_init(|| {
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || forward_stdin(sender));
+            Mutex::new(StdinBuffer {
+                receiver,
+                buffer: vec![],
+                ended: false,
+            })
+        });
+        stdin.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Moves whatever the reader thread has sent so far into the buffer.
+    fn fill(&mut self) -> io::Result<()> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(chunk) => self.buffer.extend(chunk?),
+                Err(TryRecvError::Empty) => return Ok(()),
+                Err(TryRecvError::Disconnected) => {
+                    self.ended = true;
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+impl InputScript {
+    pub fn new(data: impl Into<Vec<u8>>) -> Self {
+        InputScript {
+            data: Cursor::new(data.into()),
+        }
+    }
+}
+
+impl Input for InputScript {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        read_line(&mut self.data)
+    }
+
+    fn read_bytes(&mut self, count: usize) -> io::Result<Option<Vec<u8>>> {
+        read_bytes(&mut self.data, count)
+    }
+}
+
+impl InputFile {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(InputFile { reader })
+    }
+}
+
+impl Input for InputFile {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        read_line(&mut self.reader)
+    }
+
+    fn read_bytes(&mut self, count: usize) -> io::Result<Option<Vec<u8>>> {
+        read_bytes(&mut self.reader, count)
+    }
+}
+
+// ------------------------
+// MARK: HELPERS
+//------------------------
+
+/// Sends the standard input in chunks until it ends or fails.
+fn forward_stdin(sender: Sender<io::Result<Vec<u8>>>) {
+    let mut chunk = [0; 4096];
+    loop {
+        let read = match io::stdin().lock().read(&mut chunk) {
+            Ok(0) => return,
+            Ok(len) => Ok(chunk[..len].to_vec()),
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => Err(error),
+        };
+        let failed = read.is_err();
+        if sender.send(read).is_err() || failed {
+            return;
+        }
+    }
+}
+
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+
+    let end = line.trim_end_matches(['\n', '\r']).len();
+    line.truncate(end);
+    Ok(Some(line))
+}
+
+fn read_bytes(reader: &mut impl BufRead, count: usize) -> io::Result<Option<Vec<u8>>> {
+    let mut bytes = vec![];
+    reader.take(count as u64).read_to_end(&mut bytes)?;
+    match bytes.is_empty() && count > 0 {
+        true => Ok(None),
+        false => Ok(Some(bytes)),
+    }
+}
@@ -1,16 +1,26 @@
-#![feature(inherent_associated_types)]
-#![feature(generic_const_exprs)]
-#![feature(array_repeat)]
 #![allow(dead_code)]
-#![feature(associated_type_defaults)]
 #![feature(random)]
 
+mod binary;
 mod bytecode;
+mod error;
+mod heap;
+mod input;
+mod natives;
+mod output;
 mod stack;
 mod traits;
+mod verifier;
 mod vm;
 
+pub use binary::*;
 pub use bytecode::*;
+pub use error::*;
+pub use heap::*;
+pub use input::*;
+pub use natives::*;
+pub use output::*;
 pub use stack::*;
 pub use traits::*;
+pub use verifier::*;
 pub use vm::*;
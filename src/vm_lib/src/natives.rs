@@ -0,0 +1,79 @@
+use std::{collections::HashMap, fmt, rc::Rc};
+
+use crate::{ExecError, NativeType};
+
+// ------------------------
+// MARK: TYPES
+//------------------------
+
+/// Host function: takes its arguments oldest first and returns the value left in the
+/// accumulator. Errors crash the calling process like any other instruction.
+pub type NativeFn<D> = Rc<dyn Fn(&[D]) -> Result<D, ExecError<D>>>;
+
+#[derive(Clone)]
+pub struct Native<D: NativeType> {
+    pub arity: usize,
+    pub function: NativeFn<D>,
+}
+
+/// Host functions by name, shared by every process of a machine.
+#[derive(Clone)]
+pub struct Natives<D: NativeType> {
+    functions: HashMap<String, Native<D>>,
+}
+
+// ------------------------
+// MARK: IMPLEMENTS
+//------------------------
+
+impl<D: NativeType> Natives<D> {
+    pub fn new() -> Self {
+        Natives {
+            functions: HashMap::new(),
+        }
+    }
+
+    /// Adds a function, replacing any other registered under the same name.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        arity: usize,
+        function: impl Fn(&[D]) -> Result<D, ExecError<D>> + 'static,
+    ) {
+        let function = Rc::new(function);
+        self.functions
+            .insert(name.into(), Native { arity, function });
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Native<D>> {
+        self.functions.get(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.functions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty()
+    }
+}
+
+impl<D: NativeType> Default for Natives<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D: NativeType> fmt::Debug for Native<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Native")
+            .field("arity", &self.arity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<D: NativeType> fmt::Debug for Natives<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.functions.iter()).finish()
+    }
+}
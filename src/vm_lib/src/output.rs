@@ -0,0 +1,63 @@
+use std::{cell::RefCell, rc::Rc};
+
+// ------------------------
+// MARK: TYPES
+//------------------------
+
+/// Sink shared by the processes writing to it.
+pub type SharedOutput = Rc<RefCell<dyn Output>>;
+
+/// Writes every line to the standard output.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stdout;
+
+/// Keeps every line in memory, e.g. to check what a program printed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OutputBuffer {
+    pub lines: Vec<String>,
+}
+
+// ------------------------
+// MARK: TRAITS
+//------------------------
+
+/// Where `print` and the halt report of a process go, one line at a time.
+pub trait Output {
+    fn write_line(&mut self, line: &str);
+}
+
+// ------------------------
+// MARK: IMPLEMENTS
+//------------------------
+
+impl Stdout {
+    pub fn shared() -> SharedOutput {
+        Rc::new(RefCell::new(Stdout))
+    }
+}
+
+impl Output for Stdout {
+    fn write_line(&mut self, line: &str) {
+        println!("{}", line);
+    }
+}
+
+impl OutputBuffer {
+    pub const fn new() -> Self {
+        OutputBuffer { lines: vec![] }
+    }
+
+    /// Everything written so far, one line each.
+    pub fn contents(&self) -> String {
+        self.lines
+            .iter()
+            .map(|line| format!("{}\n", line))
+            .collect()
+    }
+}
+
+impl Output for OutputBuffer {
+    fn write_line(&mut self, line: &str) {
+        self.lines.push(line.to_string());
+    }
+}
@@ -2,7 +2,7 @@ use std::fmt::Debug;
 
 use log::debug;
 
-use crate::NativeType;
+use crate::{ErrorKind, NativeType};
 
 pub(crate) const STACK_SIZE: usize = 1024;
 
@@ -10,71 +10,140 @@ pub(crate) const STACK_SIZE: usize = 1024;
 pub struct Stack<T: NativeType> {
     pointer: usize,
     data: Vec<T>,
+    limit: usize,
 }
 
 impl<T: NativeType> Stack<T> {
     pub fn new(stack_size: usize) -> Self {
+        Self::growable(stack_size, stack_size)
+    }
+
+    /// Starts with `initial` slots and doubles them on demand, never past `limit`.
+    pub fn growable(initial: usize, limit: usize) -> Self {
+        let limit = limit.max(1);
         Stack {
-            data: vec![T::default(); stack_size],
+            data: vec![T::default(); initial.clamp(1, limit)],
             pointer: 0,
+            limit,
         }
     }
 
     pub fn to_register(&mut self, value: T) {
-        self.store_at(self.pointer, value);
+        self.data[self.pointer] = value;
+
+        debug!("\t STACK: {:?}", self);
+    }
+
+    pub fn to_register_at(&mut self, pointer: usize, value: T) -> Result<(), ErrorKind> {
+        let pointer = self.below(pointer)?;
+        self.store_at(pointer, value)
     }
 
-    pub fn store_at(&mut self, pointer: usize, value: T) {
+    pub fn store_at(&mut self, pointer: usize, value: T) -> Result<(), ErrorKind> {
+        if pointer > self.pointer {
+            return Err(self.underflow());
+        }
         self.data[pointer] = value;
 
         debug!("\t STACK: {:?}", self);
+        Ok(())
     }
 
-    pub fn store_register(&mut self) -> usize {
+    /// Pushes the accumulator, leaving a fresh slot above it as the new accumulator.
+    pub fn store_register(&mut self) -> Result<usize, ErrorKind> {
+        if self.pointer + 1 >= self.data.len() {
+            self.grow()?;
+        }
         let pointer = self.pointer;
         self.pointer += 1;
 
         debug!("\t STACK: {:?}", self);
-        pointer
+        Ok(pointer)
     }
 
-    pub fn pop<const N: usize>(&mut self) -> [T; N] {
+    pub fn pop<const N: usize>(&mut self) -> Result<[T; N], ErrorKind> {
         let len = self.pointer;
-        self.pointer -= N;
+        self.pointer = self.below(N)?;
 
         let mut result = std::array::repeat(T::default());
         result.swap_with_slice(&mut self.data[self.pointer..len]);
 
         //info!("\t STACK: {:?}", self);
 
-        result
+        Ok(result)
     }
 
-    pub fn swap(&mut self) {
-        self.data.swap(self.pointer - 1, self.pointer);
+    /// Drops every value above `depth`, carrying the accumulator down with it.
+    pub fn truncate(&mut self, depth: usize) -> Result<(), ErrorKind> {
+        if depth > self.pointer {
+            return Err(self.underflow());
+        }
+
+        let accumulator = std::mem::take(&mut self.data[self.pointer]);
+        self.data[depth..self.pointer].fill(T::default());
+        self.pointer = depth;
+        self.data[depth] = accumulator;
 
         debug!("\t STACK: {:?}", self);
+        Ok(())
     }
 
-    pub fn peek<const N: usize>(&self) -> [T; N] {
+    pub fn swap(&mut self) -> Result<(), ErrorKind> {
+        let pointer = self.below(1)?;
+        self.data.swap(pointer, self.pointer);
+
+        debug!("\t STACK: {:?}", self);
+        Ok(())
+    }
+
+    pub fn peek<const N: usize>(&self) -> Result<[T; N], ErrorKind> {
         let len = self.pointer;
-        let pointer = self.pointer - N;
+        let pointer = self.below(N)?;
 
         let mut result = std::array::repeat(T::default());
         result.clone_from_slice(&self.data[pointer..len]);
 
         //info!("\t STACK: {:?}", self);
 
-        result
+        Ok(result)
+    }
+
+    pub fn peek_at(&self, pointer: usize) -> Result<&T, ErrorKind> {
+        match pointer <= self.pointer {
+            true => Ok(&self.data[pointer]),
+            false => Err(self.underflow()),
+        }
     }
-    pub fn peek_at(&self, pointer: usize) -> &T {
-        &self.data[pointer]
+
+    pub fn peek_register(&self, pointer: usize) -> Result<&T, ErrorKind> {
+        Ok(&self.data[self.below(pointer)?])
     }
 
-    pub fn peek_register(&self, pointer: usize) -> &T {
-        let pointer = self.pointer - pointer;
-        &self.data[pointer]
+    pub fn peek_register_mut(&mut self, pointer: usize) -> Result<&mut T, ErrorKind> {
+        let pointer = self.below(pointer)?;
+        Ok(&mut self.data[pointer])
     }
+
+    /// Removes the top `count` values, oldest first, keeping the accumulator.
+    pub fn drain(&mut self, count: usize) -> Result<Vec<T>, ErrorKind> {
+        let depth = self.below(count)?;
+        let values = self.data[depth..self.pointer]
+            .iter_mut()
+            .map(std::mem::take)
+            .collect();
+        self.truncate(depth)?;
+        Ok(values)
+    }
+
+    pub fn accumulator(&self) -> &T {
+        &self.data[self.pointer]
+    }
+
+    /// Stored values followed by the accumulator.
+    pub fn values(&self) -> &[T] {
+        &self.data[..=self.pointer]
+    }
+
     pub fn len(&self) -> usize {
         self.pointer
     }
@@ -82,4 +151,44 @@ impl<T: NativeType> Stack<T> {
     pub fn is_empty(&self) -> bool {
         self.pointer == 0
     }
+
+    /// Slots the stack may grow to, including the one taken by the accumulator.
+    pub fn capacity(&self) -> usize {
+        self.limit
+    }
+
+    /// Slots currently allocated.
+    pub fn allocated(&self) -> usize {
+        self.data.len()
+    }
+
+    fn grow(&mut self) -> Result<(), ErrorKind> {
+        let len = self.data.len();
+        if len >= self.limit {
+            return Err(self.overflow());
+        }
+        self.data.resize((len * 2).min(self.limit), T::default());
+
+        debug!("\t STACK GREW TO {} SLOTS", self.data.len());
+        Ok(())
+    }
+
+    /// Absolute index `offset` slots below the accumulator.
+    pub fn below(&self, offset: usize) -> Result<usize, ErrorKind> {
+        self.pointer.checked_sub(offset).ok_or(self.underflow())
+    }
+
+    fn underflow(&self) -> ErrorKind {
+        ErrorKind::StackUnderflow {
+            depth: self.pointer,
+            capacity: self.capacity(),
+        }
+    }
+
+    fn overflow(&self) -> ErrorKind {
+        ErrorKind::StackOverflow {
+            depth: self.pointer,
+            capacity: self.capacity(),
+        }
+    }
 }
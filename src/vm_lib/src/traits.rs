@@ -1,6 +1,8 @@
-use std::fmt::Debug;
+use std::{any::Any, fmt::Debug};
 
-use crate::ProcessContext;
+use crate::{
+    CompileError, ConstantPool, ExecError, Labels, ProcessContext, ProcessStatus, Quantum,
+};
 
 // ------------------------
 // MARK: TYPES
@@ -8,21 +10,38 @@ use crate::ProcessContext;
 
 pub type FunctionOps<Op> = Vec<Op>;
 
+pub type ExecResult<D> = Result<(), ExecError<D>>;
+
 // ------------------------
 // MARK: TRAITS
 //------------------------
 
 pub trait NativeType
 where
-    Self: Debug + Clone + Default + PartialEq,
+    Self: Debug + Clone + Default + PartialEq + 'static,
 {
+    /// Reports every heap pointer held by the value, including nested ones.
+    fn trace(&self, _mark: &mut dyn FnMut(u64)) {}
+
+    /// Bytes owned by the value, including whatever its boxes point to.
+    fn size(&self) -> usize {
+        size_of_val(self)
+    }
 }
 
 pub trait Executable<D: NativeType>
 where
     Self: Debug + Clone + Sized + PartialEq + 'static,
 {
-    fn execute(&self, proc: &mut ProcessContext<D>) -> ();
+    fn execute(&self, proc: &mut ProcessContext<D>) -> ExecResult<D>;
+
+    /// Moves inline constants into the pool during `ProgramCode::compile`.
+    fn hoist_constants(&mut self, _pool: &mut ConstantPool<D>) {}
+
+    /// Replaces label references with targets reachable from `ipointer`.
+    fn resolve_labels(&mut self, _ipointer: usize, _labels: &Labels) -> Result<(), CompileError> {
+        Ok(())
+    }
 }
 
 pub trait Compilable<D: NativeType>
@@ -40,7 +59,16 @@ pub trait OpManager<D: NativeType> {
 }
 
 pub trait Runnable<D: NativeType> {
-    fn run(&mut self) -> ();
+    /// Executes instructions until the quantum runs out or the process stops.
+    fn run(&mut self, quantum: &Quantum) -> ProcessStatus<D>;
 
     fn is_finished(&self) -> bool;
+
+    fn pid(&self) -> usize;
+
+    /// Values the garbage collector must keep alive: stack, accumulator and constants.
+    fn roots(&self) -> Vec<&D>;
+
+    /// Lets the machine hand the process back with its concrete type.
+    fn as_any_mut(&mut self) -> &mut dyn Any;
 }
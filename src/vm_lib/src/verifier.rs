@@ -0,0 +1,254 @@
+use std::fmt::{self, Display};
+
+use crate::{ByteCode, Executable, NativeType};
+
+// ------------------------
+// MARK: TYPES
+//------------------------
+
+/// Instruction a jump lands on, after the run loop's increment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    Static(usize),
+    // Computed at run time, the path is not followed
+    Dynamic,
+}
+
+/// How an instruction hands control over, as seen by the verifier.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flow {
+    Next,
+    Jump(Target),
+    // Either falls through or jumps
+    Branch(Target),
+    // Enters a subroutine and continues after it once it returns
+    Call(Target),
+    Return,
+    Halt,
+}
+
+/// Stack slots an instruction reads below the top, removes and adds.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StackEffect {
+    pub reads: usize,
+    pub pops: usize,
+    pub pushes: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiagnosticKind {
+    InvalidOperand(String),
+    JumpOutOfBounds { target: usize },
+    FallsOffEnd,
+    NoTermination,
+    ReturnOutsideCall,
+    StackUnderflow { depth: usize, required: usize },
+    UnbalancedStack { expected: usize, found: usize },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub ipointer: usize,
+    pub kind: DiagnosticKind,
+}
+
+/// Abstract stack before an instruction, relative to the entry of the current routine.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct State {
+    depth: usize,
+    in_call: bool,
+}
+
+struct Walker {
+    states: Vec<Option<State>>,
+    unbalanced: Vec<bool>,
+    worklist: Vec<usize>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+// ------------------------
+// MARK: TRAITS
+//------------------------
+
+pub trait Verifiable<D: NativeType>
+where
+    Self: Executable<D>,
+{
+    fn flow(&self, ipointer: usize, constants: &[D]) -> Flow;
+
+    fn stack_effect(&self) -> StackEffect;
+
+    /// Problems visible from the instruction alone, e.g. writing into a constant.
+    fn check(&self, _constants: &[D]) -> Vec<String> {
+        vec![]
+    }
+}
+
+// ------------------------
+// MARK: IMPLEMENTS
+//------------------------
+
+impl<Op, D> ByteCode<Op, D>
+where
+    Op: Verifiable<D>,
+    D: NativeType,
+{
+    /// Walks every path from the entry point and from each called subroutine, checking
+    /// jump targets, termination and that the stack depth agrees wherever paths meet.
+    ///
+    /// Inside a subroutine the caller's values are unknown, so reads below the frame
+    /// are allowed but removing them is not.
+    pub fn verify(&self) -> Result<(), Vec<Diagnostic>> {
+        let code = self.get();
+        let mut walker = Walker::new(code.len());
+        for (ipointer, op) in code.iter().enumerate() {
+            for message in op.check(self.get_constants()) {
+                walker.report(ipointer, DiagnosticKind::InvalidOperand(message));
+            }
+        }
+
+        let entry = State {
+            depth: 0,
+            in_call: false,
+        };
+        let mut halts = false;
+        walker.enter(0, entry);
+        while let Some(ipointer) = walker.worklist.pop() {
+            let op = &code[ipointer];
+            let state = walker.states[ipointer].expect("queued instructions have a state");
+
+            let effect = op.stack_effect();
+            let required = match state.in_call {
+                true => effect.pops,
+                false => effect.pops.max(effect.reads),
+            };
+            if state.depth < required {
+                let depth = state.depth;
+                walker.report(ipointer, DiagnosticKind::StackUnderflow { depth, required });
+            }
+            let after = State {
+                depth: state.depth.saturating_sub(effect.pops) + effect.pushes,
+                ..state
+            };
+
+            match op.flow(ipointer, self.get_constants()) {
+                Flow::Next => walker.fall_through(ipointer, after),
+                Flow::Jump(target) => walker.jump(ipointer, target, after),
+                Flow::Branch(target) => {
+                    walker.jump(ipointer, target, after);
+                    walker.fall_through(ipointer, after);
+                }
+                Flow::Call(target) => {
+                    let callee = State {
+                        depth: 0,
+                        in_call: true,
+                    };
+                    walker.jump(ipointer, target, callee);
+                    walker.fall_through(ipointer, after);
+                }
+                Flow::Return if !state.in_call => {
+                    walker.report(ipointer, DiagnosticKind::ReturnOutsideCall)
+                }
+                Flow::Return => {}
+                Flow::Halt => halts = true,
+            }
+        }
+
+        if !halts {
+            walker.report(0, DiagnosticKind::NoTermination);
+        }
+
+        let mut diagnostics = walker.diagnostics;
+        match diagnostics.is_empty() {
+            true => Ok(()),
+            false => {
+                diagnostics.sort_by_key(|diagnostic| diagnostic.ipointer);
+                Err(diagnostics)
+            }
+        }
+    }
+}
+
+impl Walker {
+    fn new(len: usize) -> Self {
+        Walker {
+            states: vec![None; len],
+            unbalanced: vec![false; len],
+            worklist: vec![],
+            diagnostics: vec![],
+        }
+    }
+
+    fn report(&mut self, ipointer: usize, kind: DiagnosticKind) {
+        self.diagnostics.push(Diagnostic { ipointer, kind });
+    }
+
+    fn fall_through(&mut self, ipointer: usize, state: State) {
+        match ipointer + 1 < self.states.len() {
+            true => self.enter(ipointer + 1, state),
+            false => self.report(ipointer, DiagnosticKind::FallsOffEnd),
+        }
+    }
+
+    fn jump(&mut self, ipointer: usize, target: Target, state: State) {
+        match target {
+            Target::Static(target) if target < self.states.len() => self.enter(target, state),
+            Target::Static(target) => {
+                self.report(ipointer, DiagnosticKind::JumpOutOfBounds { target })
+            }
+            Target::Dynamic => {}
+        }
+    }
+
+    /// Queues `ipointer` the first time it is reached and compares depths afterwards.
+    fn enter(&mut self, ipointer: usize, state: State) {
+        let Some(slot) = self.states.get_mut(ipointer) else {
+            return self.report(ipointer, DiagnosticKind::FallsOffEnd);
+        };
+        match *slot {
+            None => {
+                *slot = Some(state);
+                self.worklist.push(ipointer);
+            }
+            Some(known) if known.depth != state.depth && !self.unbalanced[ipointer] => {
+                self.unbalanced[ipointer] = true;
+                let kind = DiagnosticKind::UnbalancedStack {
+                    expected: known.depth,
+                    found: state.depth,
+                };
+                self.report(ipointer, kind);
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+impl Display for DiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiagnosticKind::InvalidOperand(message) => f.write_str(message),
+            DiagnosticKind::JumpOutOfBounds { target } => {
+                write!(f, "jump target {} is outside the program", target)
+            }
+            DiagnosticKind::FallsOffEnd => f.write_str("execution runs past the last instruction"),
+            DiagnosticKind::NoTermination => f.write_str("no HALT is reachable from the entry"),
+            DiagnosticKind::ReturnOutsideCall => f.write_str("return outside of a call"),
+            DiagnosticKind::StackUnderflow { depth, required } => write!(
+                f,
+                "needs {} values on the stack but only {} are there",
+                required, depth
+            ),
+            DiagnosticKind::UnbalancedStack { expected, found } => write!(
+                f,
+                "stack depth {} differs from {} on another path",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}: {}", self.ipointer, self.kind)
+    }
+}
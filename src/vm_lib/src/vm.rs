@@ -1,18 +1,95 @@
-use std::random::random;
+use std::{
+    any::Any,
+    cell::{RefCell, RefMut},
+    collections::BTreeSet,
+    io,
+    random::random,
+    rc::Rc,
+    task::Poll,
+    time::{Duration, Instant},
+};
 
-use log::{debug, trace, warn};
+use log::{debug, error, trace, warn};
 
-use crate::{Executable, NativeType, ProgramCode, Runnable, Stack, bytecode::ByteCode};
+use crate::{
+    CompileError, ErrorKind, ExecError, Executable, GcConfig, Heap, NativeType, Natives,
+    ProgramCode, Runnable, SharedInput, SharedOutput, Stack, Stdin, Stdout, Verifiable, VmError,
+    bytecode::ByteCode,
+};
+
+/// Instructions executed between clock reads when the quantum is a time slice.
+const TIME_CHECK_INTERVAL: usize = 256;
 
 // ------------------------
 // MARK: TYPES
 //------------------------
 
+/// Budget a process may consume on each turn before yielding to the next one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Quantum {
+    Instructions(usize),
+    Time(Duration),
+}
+
+/// What integer arithmetic does when the result does not fit its type.
+///
+/// Division and modulo by zero have no result to wrap or saturate to, so they raise
+/// `ErrorKind::DivisionByZero` under every policy.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OverflowPolicy {
+    #[default]
+    Wrapping,
+    // Raise `ErrorKind::IntegerOverflow`
+    Checked,
+    Saturating,
+}
+
+/// Stack given to each new process: allocated with `initial` slots and grown up to `limit`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StackSize {
+    pub initial: usize,
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProcessStatus<D: NativeType> {
+    Ready,
+    Finished,
+    Blocked,
+    Crashed(VmError<D>),
+}
+
+/// Why a debugger call handed control back.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stop<D: NativeType> {
+    Step,
+    // About to execute the instruction with a breakpoint
+    Breakpoint(usize),
+    // Waiting for input, the same instruction runs again on the next call
+    Blocked,
+    Finished,
+    Crashed(VmError<D>),
+}
+
+/// Return address and stack depth saved by a `call`, restored by the matching `ret`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CallFrame {
+    pub return_ip: usize,
+    pub stack_base: usize,
+}
+
 pub struct ProcessContext<D: NativeType> {
     pub stack: Stack<D>,
+    constants: Rc<[D]>,
+    heap: Rc<RefCell<Heap<D>>>,
+    natives: Rc<RefCell<Natives<D>>>,
+    output: SharedOutput,
+    input: SharedInput,
+    overflow: OverflowPolicy,
     ipointer: usize,
-    calls_history: Vec<usize>,
+    calls_history: Vec<CallFrame>,
     is_finished: bool,
+    is_blocked: bool,
     run_timer: std::time::Instant,
 }
 
@@ -25,67 +102,351 @@ where
     //
     code: ByteCode<Op, D>,
     context: ProcessContext<D>,
+    // Only honored by the debugger, the scheduler runs through them
+    breakpoints: BTreeSet<usize>,
 }
 
 pub struct StackMachine<D: NativeType> {
     //
-    pub heap: Stack<D>,
+    pub heap: Rc<RefCell<Heap<D>>>,
+    // Shared with every process, so functions registered later are visible to them
+    pub natives: Rc<RefCell<Natives<D>>>,
     pub proceses: Vec<Box<dyn Runnable<D>>>,
+    pub quantum: Quantum,
+    pub stack_size: StackSize,
+    // Taken by each process when it is added
+    pub overflow: OverflowPolicy,
+    // Taken by each process when it is added, standard output by default
+    pub output: SharedOutput,
+    // Taken by each process when it is added, standard input by default
+    pub input: SharedInput,
+    pub gc: GcConfig,
+    next_collection: usize,
 }
 
 // ------------------------
 // MARK: IMPLEMENTS
 //------------------------
 
+impl Default for Quantum {
+    fn default() -> Self {
+        Quantum::Instructions(10_000)
+    }
+}
+
+impl Quantum {
+    #[inline]
+    pub fn is_exhausted(&self, executed: usize, started: Instant) -> bool {
+        match self {
+            Quantum::Instructions(budget) => executed >= *budget,
+            Quantum::Time(slice) => {
+                executed.is_multiple_of(TIME_CHECK_INTERVAL) && started.elapsed() >= *slice
+            }
+        }
+    }
+}
+
+impl Default for StackSize {
+    fn default() -> Self {
+        StackSize {
+            initial: 16,
+            limit: 1 << 16,
+        }
+    }
+}
+
+impl<D: NativeType + 'static> Default for StackMachine<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<D: NativeType + 'static> StackMachine<D> {
     pub fn new() -> Self {
+        Self::with_quantum(Quantum::default())
+    }
+
+    pub fn with_quantum(quantum: Quantum) -> Self {
         StackMachine {
-            heap: Stack::<D>::new(1024),
+            heap: Rc::new(RefCell::new(Heap::new())),
+            natives: Rc::new(RefCell::new(Natives::new())),
             proceses: vec![],
+            quantum,
+            stack_size: StackSize::default(),
+            overflow: OverflowPolicy::default(),
+            output: Stdout::shared(),
+            input: Stdin::shared(),
+            gc: GcConfig::default(),
+            next_collection: 0,
         }
     }
 
-    pub fn run(&mut self) {
-        let mut running_process = 0;
-        while self.proceses.len() > 0 {
-            if running_process >= self.proceses.len() {
-                running_process = 0;
-            }
+    /// Runs every process until it halts or fails, returning the errors of the failed ones.
+    pub fn run(&mut self) -> Vec<VmError<D>> {
+        let mut errors = vec![];
+        while !self.proceses.is_empty() {
+            errors.extend(self.step());
+        }
 
+        errors
+    }
+
+    /// Gives every process one quantum, in order, dropping the ones that finish or crash.
+    /// Yields the thread when every process is blocked.
+    pub fn step(&mut self) -> Vec<VmError<D>> {
+        let mut errors = vec![];
+        let mut running_process = 0;
+        let mut all_blocked = true;
+        while running_process < self.proceses.len() {
             let process = self.proceses[running_process].as_mut();
-            process.run();
 
-            if process.is_finished() {
-                self.proceses.remove(running_process);
-                continue;
+            let status = process.run(&self.quantum);
+            all_blocked &= status == ProcessStatus::Blocked;
+            match status {
+                ProcessStatus::Ready | ProcessStatus::Blocked => {
+                    running_process += 1;
+                }
+                ProcessStatus::Finished => {
+                    self.proceses.remove(running_process);
+                }
+                ProcessStatus::Crashed(err) => {
+                    error!("PROCESS CRASHED: {}", err);
+                    errors.push(err);
+                    self.proceses.remove(running_process);
+                }
             }
 
-            running_process += 1;
+            if self.heap.borrow().len() >= self.next_collection.max(self.gc.threshold) {
+                self.collect_garbage();
+            }
         }
+
+        if all_blocked && !self.proceses.is_empty() {
+            std::thread::yield_now();
+        }
+        errors
+    }
+
+    /// Frees the heap cells no process can reach and schedules the next collection.
+    pub fn collect_garbage(&mut self) -> usize {
+        let mut heap = self.heap.borrow_mut();
+        let roots = self.proceses.iter().flat_map(|process| process.roots());
+        let reclaimed = heap.collect(roots);
+
+        self.next_collection = (heap.len() * self.gc.growth).max(self.gc.threshold);
+        reclaimed
+    }
+
+    /// Makes a host function callable from bytecode by `name`, taking `arity` arguments.
+    pub fn register_native(
+        &mut self,
+        name: impl Into<String>,
+        arity: usize,
+        function: impl Fn(&[D]) -> Result<D, ExecError<D>> + 'static,
+    ) {
+        self.natives.borrow_mut().register(name, arity, function);
+    }
+
+    /// Compiles the program and schedules it like `add_bytecode`, returning its pid.
+    pub fn add_process<Op: Verifiable<D>>(
+        &mut self,
+        program_code: ProgramCode<Op, D>,
+    ) -> Result<usize, CompileError> {
+        self.add_bytecode(program_code.compile()?)
+    }
+
+    /// Like `add_process`, printing to `output` instead of the machine's sink.
+    pub fn add_process_with_output<Op: Verifiable<D>>(
+        &mut self,
+        program_code: ProgramCode<Op, D>,
+        output: SharedOutput,
+    ) -> Result<usize, CompileError> {
+        self.schedule(program_code.compile()?, output)
+    }
+
+    /// Verifies already compiled code, e.g. read from a file, before scheduling it.
+    pub fn add_bytecode<Op: Verifiable<D>>(
+        &mut self,
+        bytecode: ByteCode<Op, D>,
+    ) -> Result<usize, CompileError> {
+        self.schedule(bytecode, self.output.clone())
     }
 
-    pub fn add_process<Op: Executable<D>>(&mut self, program_code: ProgramCode<Op, D>) {
-        let bytecode = program_code.compile();
-        let process = Box::new(Process::new(64, bytecode));
-        self.proceses.push(process);
+    fn schedule<Op: Verifiable<D>>(
+        &mut self,
+        bytecode: ByteCode<Op, D>,
+        output: SharedOutput,
+    ) -> Result<usize, CompileError> {
+        bytecode.verify().map_err(CompileError::Verification)?;
+        let process = Process::new(self.stack_size, self.heap.clone(), bytecode);
+        let process = process
+            .with_overflow(self.overflow)
+            .with_natives(self.natives.clone())
+            .with_output(output)
+            .with_input(self.input.clone());
+        let pid = process.pid;
+        self.proceses.push(Box::new(process));
+        Ok(pid)
+    }
+
+    /// Scheduled process `pid`, e.g. to set breakpoints or step it in the debugger.
+    /// `None` once it has stopped or when it does not run `Op` instructions.
+    pub fn process_mut<Op: Executable<D>>(&mut self, pid: usize) -> Option<&mut Process<Op, D>> {
+        let process = self
+            .proceses
+            .iter_mut()
+            .find(|process| process.pid() == pid)?;
+        process.as_any_mut().downcast_mut()
     }
 }
 
 impl<Op: Executable<D>, D: NativeType> Process<Op, D> {
-    pub fn new(stack_size: usize, code: ByteCode<Op, D>) -> Self {
+    pub fn new(stack_size: StackSize, heap: Rc<RefCell<Heap<D>>>, code: ByteCode<Op, D>) -> Self {
         Process {
-            pid: random(),
-            code,
+            pid: random(..),
             //
             context: ProcessContext {
-                stack: Stack::<D>::new(stack_size),
+                stack: Stack::<D>::growable(stack_size.initial, stack_size.limit),
+                constants: code.share_constants(),
+                heap,
+                natives: Rc::new(RefCell::new(Natives::new())),
+                output: Stdout::shared(),
+                input: Stdin::shared(),
+                overflow: OverflowPolicy::default(),
                 run_timer: std::time::Instant::now(),
                 ipointer: 0,
                 calls_history: vec![],
                 is_finished: false,
+                is_blocked: false,
             },
+            code,
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    pub const fn with_overflow(mut self, overflow: OverflowPolicy) -> Self {
+        self.context.overflow = overflow;
+        self
+    }
+
+    pub fn with_natives(mut self, natives: Rc<RefCell<Natives<D>>>) -> Self {
+        self.context.natives = natives;
+        self
+    }
+
+    pub fn with_output(mut self, output: SharedOutput) -> Self {
+        self.context.output = output;
+        self
+    }
+
+    pub fn with_input(mut self, input: SharedInput) -> Self {
+        self.context.input = input;
+        self
+    }
+
+    /// Stack, instruction pointer and call history, as left by the last instruction.
+    pub const fn context(&self) -> &ProcessContext<D> {
+        &self.context
+    }
+
+    /// Instruction executed next.
+    pub fn current(&self) -> Option<&Op> {
+        self.code.try_get_at(self.context.ipointer)
+    }
+
+    pub fn set_breakpoint(&mut self, ipointer: usize) {
+        self.breakpoints.insert(ipointer);
+    }
+
+    /// Returns whether there was a breakpoint at `ipointer`.
+    pub fn clear_breakpoint(&mut self, ipointer: usize) -> bool {
+        self.breakpoints.remove(&ipointer)
+    }
+
+    pub const fn breakpoints(&self) -> &BTreeSet<usize> {
+        &self.breakpoints
+    }
+
+    /// Executes a single instruction.
+    pub fn step(&mut self) -> Stop<D> {
+        if self.context.is_finished {
+            return Stop::Finished;
+        }
+        match self.advance() {
+            None | Some(ProcessStatus::Ready) => Stop::Step,
+            Some(ProcessStatus::Blocked) => Stop::Blocked,
+            Some(ProcessStatus::Finished) => Stop::Finished,
+            Some(ProcessStatus::Crashed(err)) => Stop::Crashed(err),
+        }
+    }
+
+    /// Like `step`, but runs a called subroutine until it returns, unless it hits a breakpoint.
+    pub fn step_over(&mut self) -> Stop<D> {
+        let depth = self.context.calls_history.len();
+        match self.step() {
+            Stop::Step if self.context.calls_history.len() > depth => self.run_until(Some(depth)),
+            stop => stop,
+        }
+    }
+
+    /// Runs until the next breakpoint or until the process stops. The current instruction
+    /// is executed even if it has a breakpoint, so resuming from one moves on.
+    pub fn resume(&mut self) -> Stop<D> {
+        match self.step() {
+            Stop::Step => self.run_until(None),
+            stop => stop,
+        }
+    }
+
+    /// Steps until a breakpoint or, when given, until the call stack is back to `depth`.
+    fn run_until(&mut self, depth: Option<usize>) -> Stop<D> {
+        loop {
+            let ipointer = self.context.ipointer;
+            if self.breakpoints.contains(&ipointer) {
+                return Stop::Breakpoint(ipointer);
+            }
+            match self.step() {
+                Stop::Step => {}
+                stop => return stop,
+            }
+            if depth.is_some_and(|depth| self.context.calls_history.len() <= depth) {
+                return Stop::Step;
+            }
         }
     }
+
+    /// Executes the instruction at the instruction pointer, returning the status it
+    /// leaves the process in, or `None` if it can go on.
+    #[inline]
+    fn advance(&mut self) -> Option<ProcessStatus<D>> {
+        let ipointer = self.context.ipointer;
+        let Some(op) = self.code.try_get_at(ipointer) else {
+            self.context.is_finished = true;
+            return Some(ProcessStatus::Crashed(VmError::out_of_bounds(
+                self.pid, ipointer,
+            )));
+        };
+
+        if let Err(err) = op.execute(&mut self.context) {
+            self.context.is_finished = true;
+            return Some(ProcessStatus::Crashed(VmError::new(
+                err, self.pid, ipointer, op,
+            )));
+        }
+
+        // Retried on the next turn
+        if self.context.is_blocked {
+            self.context.is_blocked = false;
+            return Some(ProcessStatus::Blocked);
+        }
+
+        // Wraps only when a jump lands on instruction 0, targets are validated
+        self.context.ipointer = self.context.ipointer.wrapping_add(1);
+        if self.context.is_finished {
+            return Some(ProcessStatus::Finished);
+        }
+        None
+    }
 }
 
 impl<D: NativeType> ProcessContext<D> {
@@ -105,13 +466,102 @@ impl<D: NativeType> ProcessContext<D> {
         self.get_ipntr().overflowing_add_signed(offset).0
     }
 
+    pub const fn overflow(&self) -> OverflowPolicy {
+        self.overflow
+    }
+
+    pub fn get_constant(&self, index: usize) -> Result<&D, ErrorKind> {
+        self.constants
+            .get(index)
+            .ok_or(ErrorKind::ConstantOutOfBounds)
+    }
+
+    /// Heap shared with every other process of the machine.
+    pub fn heap(&self) -> RefMut<'_, Heap<D>> {
+        self.heap.borrow_mut()
+    }
+
+    /// Runs the host function registered as `name` on the top `count` stored values,
+    /// which it consumes, leaving its result in the accumulator.
+    pub fn call_native(&mut self, name: &str, count: usize) -> Result<(), ExecError<D>> {
+        let native = self.natives.borrow().get(name).cloned();
+        let native = native.ok_or(ErrorKind::UnknownFunction)?;
+        if native.arity != count {
+            return Err(ErrorKind::LengthMismatch.into());
+        }
+
+        let args = self.stack.drain(count)?;
+        debug!("\t NATIVE CALL: {} {:?}", name, args);
+        let result = (native.function)(&args)?;
+        self.stack.to_register(result);
+        Ok(())
+    }
+
+    /// Jumps to `target`, remembering where to come back and how deep the stack was.
+    pub fn call(&mut self, target: usize) {
+        self.calls_history.push(CallFrame {
+            return_ip: self.ipointer,
+            stack_base: self.stack.len(),
+        });
+        self.goto(target);
+    }
+
+    /// Drops the callee's stack values, keeping the accumulator as the return value,
+    /// and resumes right after the matching `call`.
+    pub fn ret(&mut self) -> Result<(), ErrorKind> {
+        let frame = self
+            .calls_history
+            .pop()
+            .ok_or(ErrorKind::ReturnWithoutCall)?;
+
+        self.stack.truncate(frame.stack_base)?;
+        self.goto(frame.return_ip);
+        Ok(())
+    }
+
+    pub fn calls_history(&self) -> &[CallFrame] {
+        &self.calls_history
+    }
+
+    /// Ends the current quantum early, reporting the process as blocked. The current
+    /// instruction runs again on its next turn, so it must not have changed anything.
+    pub fn block(&mut self) {
+        self.is_blocked = true;
+    }
+
     pub fn print(&self, arg: &D) {
         trace!("\t PRINTING: {:?}", arg);
-        println!("{:?}", arg);
+        self.output.borrow_mut().write_line(&format!("{:?}", arg));
+    }
+
+    /// Next line of input, `None` once it has ended. Blocks the process while no line
+    /// is ready.
+    pub fn read_line(&mut self) -> Result<Poll<Option<String>>, ErrorKind> {
+        let line = self.input.borrow_mut().read_line();
+        self.poll(line)
+    }
+
+    /// Up to `count` bytes of input, `None` once it has ended. Blocks the process while
+    /// they are not ready.
+    pub fn read_bytes(&mut self, count: usize) -> Result<Poll<Option<Vec<u8>>>, ErrorKind> {
+        let bytes = self.input.borrow_mut().read_bytes(count);
+        self.poll(bytes)
+    }
+
+    fn poll<T>(&mut self, read: io::Result<T>) -> Result<Poll<T>, ErrorKind> {
+        match read {
+            Ok(value) => Ok(Poll::Ready(value)),
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+                self.block();
+                Ok(Poll::Pending)
+            }
+            Err(error) => Err(ErrorKind::InputFailed(error.to_string())),
+        }
     }
 
     pub fn halt(&mut self) {
-        println!("Execution time: {:?}", self.run_timer.elapsed());
+        let report = format!("Execution time: {:?}", self.run_timer.elapsed());
+        self.output.borrow_mut().write_line(&report);
 
         warn!("EXITING VM");
 
@@ -127,15 +577,17 @@ impl<D: NativeType> ProcessContext<D> {
 
 impl<Op: Executable<D>, D: NativeType> Runnable<D> for Process<Op, D> {
     #[inline]
-    fn run(&mut self) {
+    fn run(&mut self, quantum: &Quantum) -> ProcessStatus<D> {
+        let started = Instant::now();
+        let mut executed = 0;
         loop {
-            self.code
-                .get_at(self.context.ipointer)
-                .execute(&mut self.context);
+            if let Some(status) = self.advance() {
+                return status;
+            }
 
-            self.context.ipointer += 1;
-            if self.context.is_finished {
-                break;
+            executed += 1;
+            if quantum.is_exhausted(executed, started) {
+                return ProcessStatus::Ready;
             }
         }
     }
@@ -144,4 +596,18 @@ impl<Op: Executable<D>, D: NativeType> Runnable<D> for Process<Op, D> {
     fn is_finished(&self) -> bool {
         self.context.is_finished
     }
+
+    #[inline]
+    fn pid(&self) -> usize {
+        self.pid
+    }
+
+    fn roots(&self) -> Vec<&D> {
+        let stack = self.context.stack.values().iter();
+        stack.chain(self.context.constants.iter()).collect()
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 }
@@ -0,0 +1,694 @@
+//! Textual assembly for [`Instruction`].
+//!
+//! One instruction per line, operands separated by commas and `;` starting a comment:
+//!
+//! ```text
+//! .const "pooled constant"      ; appended to the constant pool
+//!         STORE 1.0
+//! loop:   LT 1e12, $0           ; `$n` is `Arg::Ref(n)`, `acc` is `Arg::Acc`
+//!         JUMPIF acc, end
+//!         MUL $0, 1.000001
+//!         COPY acc, $0
+//!         JUMP loop
+//! end:    PRINT $0
+//!         PRINT #0              ; `#n` is `Arg::ConstIdx(n)`
+//!         HALT
+//! ```
+//!
+//! Constants are written as `Data` literals: `42`, `-1.5`, `nan`, `inf`, `7u8`, `true`,
+//! `none`, `"text"`, `x"00ff"` (byte array), `fn"name"`, `@3` (pointer), `(a, b)`,
+//! `[a, b]` and `{key: value}`. Labels may only be used as `JUMP`/`JUMPIF`/`CALL` targets; they are
+//! defined on the resulting [`ProgramCode`] and resolved by `compile`.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use vm_lib::ProgramCode;
+
+use crate::{
+    collections::CollectionKind,
+    data_types::{Arg, Data},
+    instructions::{BinaryOp, Instruction, UnaryOp},
+    types::DataType,
+};
+
+// ------------------------
+// MARK: TYPES
+//------------------------
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+type AsmResult<T> = Result<T, AsmError>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Arg(Arg),
+    Label(String, usize),
+}
+
+/// Instruction waiting for its label operands to be resolved.
+struct Pending {
+    line: usize,
+    mnemonic: String,
+    column: usize,
+    operands: Vec<Operand>,
+}
+
+struct Cursor<'a> {
+    line: usize,
+    source: &'a str,
+    pos: usize,
+}
+
+// ------------------------
+// MARK: ASSEMBLER
+//------------------------
+
+pub fn assemble(source: &str) -> AsmResult<ProgramCode<Instruction, Data>> {
+    let mut labels = HashMap::new();
+    let mut definitions = vec![];
+    let mut pending = vec![];
+    let mut constants = vec![];
+
+    for (index, text) in source.lines().enumerate() {
+        let mut cursor = Cursor::new(index + 1, text);
+
+        cursor.skip_whitespace();
+        loop {
+            let column = cursor.column();
+            let Some(label) = cursor.label()? else {
+                break;
+            };
+            if labels.insert(label.clone(), pending.len()).is_some() {
+                return Err(cursor.error_at(column, format!("duplicate label `{}`", label)));
+            }
+            definitions.push((label, pending.len()));
+            cursor.skip_whitespace();
+        }
+        if cursor.is_line_end() {
+            continue;
+        }
+
+        let column = cursor.column();
+        let mnemonic = cursor.word();
+        if mnemonic.is_empty() {
+            return Err(cursor.error("expected an instruction"));
+        }
+        let operands = cursor.operands()?;
+
+        if mnemonic.eq_ignore_ascii_case(".const") {
+            match operands.as_slice() {
+                [Operand::Arg(Arg::Const(data))] => constants.push(data.clone()),
+                _ => return Err(cursor.error_at(column, "`.const` expects one literal")),
+            }
+            continue;
+        }
+
+        pending.push(Pending {
+            line: index + 1,
+            mnemonic,
+            column,
+            operands,
+        });
+    }
+
+    let instructions = pending
+        .into_iter()
+        .map(|op| op.build(&labels))
+        .collect::<AsmResult<Vec<_>>>()?;
+
+    let mut program = ProgramCode::new(instructions, constants);
+    for (label, ipointer) in definitions {
+        program.define_label(label, ipointer);
+    }
+    Ok(program)
+}
+
+impl Pending {
+    fn build(self, labels: &HashMap<String, usize>) -> AsmResult<Instruction> {
+        let name = self.mnemonic.to_ascii_uppercase();
+        let instruction = if let Some(op) = BinaryOp::from_mnemonic(&name) {
+            let [a, b] = self.args::<2>()?;
+            Instruction::BinaryOp(op, a, b)
+        } else if let Some(op) = UnaryOp::from_mnemonic(&name) {
+            Instruction::UnaryOp(op, self.single()?)
+        } else if let Some(kind) = CollectionKind::from_mnemonic(&name) {
+            Instruction::Build(kind, self.count(self.single()?)?)
+        } else {
+            match name.as_str() {
+                "STORE" => Instruction::Store(self.single()?),
+                "LOAD" => Instruction::Load(self.single()?),
+                "COPY" => {
+                    let [src, tgt] = self.args::<2>()?;
+                    Instruction::Copy(src, tgt)
+                }
+                "FREE" => Instruction::Free(self.count(self.single()?)?),
+                "ALLOC" => Instruction::Alloc(self.single()?),
+                "DEALLOC" => Instruction::Dealloc(self.single()?),
+                "HLOAD" => Instruction::HeapLoad(self.single()?),
+                "HSTORE" => {
+                    let [ptr, value] = self.args::<2>()?;
+                    Instruction::HeapStore(ptr, value)
+                }
+                "INDEX" => {
+                    let [collection, key] = self.args::<2>()?;
+                    Instruction::Index(collection, key)
+                }
+                "SET" => {
+                    let [target, key, value] = self.args::<3>()?;
+                    Instruction::Set(target, key, value)
+                }
+                "APPEND" => {
+                    let [target, value] = self.args::<2>()?;
+                    Instruction::Append(target, value)
+                }
+                "POP" => Instruction::Pop(self.single()?),
+                "REMOVE" => {
+                    let [target, key] = self.args::<2>()?;
+                    Instruction::Remove(target, key)
+                }
+                "CONTAINS" => {
+                    let [collection, value] = self.args::<2>()?;
+                    Instruction::Contains(collection, value)
+                }
+                "LEN" => Instruction::Len(self.single()?),
+                "UNPACK" => {
+                    let [collection, count] = self.args::<2>()?;
+                    Instruction::Unpack(collection, self.count(count)?)
+                }
+                "SLICE" => {
+                    let [text, start, end] = self.args::<3>()?;
+                    Instruction::Slice(text, start, end)
+                }
+                "FIND" => {
+                    let [text, needle] = self.args::<2>()?;
+                    Instruction::Find(text, needle)
+                }
+                "REPLACE" => {
+                    let [text, from, to] = self.args::<3>()?;
+                    Instruction::Replace(text, from, to)
+                }
+                "SPLIT" => {
+                    let [text, separator] = self.args::<2>()?;
+                    Instruction::Split(text, separator)
+                }
+                "JOIN" => {
+                    let [list, separator] = self.args::<2>()?;
+                    Instruction::Join(list, separator)
+                }
+                "UPPER" => Instruction::Upper(self.single()?),
+                "LOWER" => Instruction::Lower(self.single()?),
+                "TRIM" => Instruction::Trim(self.single()?),
+                "STARTSWITH" => {
+                    let [text, prefix] = self.args::<2>()?;
+                    Instruction::StartsWith(text, prefix)
+                }
+                "ENDSWITH" => {
+                    let [text, suffix] = self.args::<2>()?;
+                    Instruction::EndsWith(text, suffix)
+                }
+                "CAST" => {
+                    self.arity(2)?;
+                    Instruction::Cast(self.arg(0)?, self.data_type(1)?)
+                }
+                "TYPEOF" => Instruction::TypeOf(self.single()?),
+                "ISTYPE" => {
+                    self.arity(2)?;
+                    Instruction::IsType(self.arg(0)?, self.data_type(1)?)
+                }
+                "JUMP" => Instruction::Jump(self.target(0, labels)?),
+                "JUMPIF" => {
+                    self.arity(2)?;
+                    let cond = self.arg(0)?;
+                    Instruction::JumpIf(cond, self.target(1, labels)?)
+                }
+                "CALL" => Instruction::Call(self.target(0, labels)?),
+                "CALLNATIVE" => {
+                    let [function, count] = self.args::<2>()?;
+                    Instruction::CallNative(function, self.count(count)?)
+                }
+                "RETURN" => {
+                    self.arity(0)?;
+                    Instruction::Return
+                }
+                "PRINT" => Instruction::Print(self.single()?),
+                "READLINE" => {
+                    self.arity(0)?;
+                    Instruction::ReadLine
+                }
+                "READBYTES" => Instruction::ReadBytes(self.single()?),
+                "HALT" => {
+                    self.arity(0)?;
+                    Instruction::HALT
+                }
+                _ => {
+                    let message = format!("unknown instruction `{}`", self.mnemonic);
+                    return Err(self.error(message));
+                }
+            }
+        };
+        Ok(instruction)
+    }
+
+    fn arity(&self, count: usize) -> AsmResult<()> {
+        if self.operands.len() != count {
+            let message = format!(
+                "`{}` expects {} operand(s), found {}",
+                self.mnemonic,
+                count,
+                self.operands.len()
+            );
+            return Err(self.error(message));
+        }
+        Ok(())
+    }
+
+    fn arg(&self, position: usize) -> AsmResult<Arg> {
+        match &self.operands[position] {
+            Operand::Arg(arg) => Ok(arg.clone()),
+            Operand::Label(label, column) => Err(AsmError {
+                line: self.line,
+                column: *column,
+                message: format!("label `{}` is only allowed as a jump target", label),
+            }),
+        }
+    }
+
+    fn single(&self) -> AsmResult<Arg> {
+        self.arity(1)?;
+        self.arg(0)
+    }
+
+    fn args<const N: usize>(&self) -> AsmResult<[Arg; N]> {
+        self.arity(N)?;
+        let mut args: [Arg; N] = std::array::repeat(Arg::Acc);
+        for (position, arg) in args.iter_mut().enumerate() {
+            *arg = self.arg(position)?;
+        }
+        Ok(args)
+    }
+
+    /// Type operand, a bare name such as `Float` or `List`.
+    fn data_type(&self, position: usize) -> AsmResult<DataType> {
+        let name = match &self.operands[position] {
+            Operand::Label(name, _) => name.as_str(),
+            // `none` is read as a literal before it gets here
+            Operand::Arg(Arg::Const(Data::None)) => "none",
+            _ => "",
+        };
+        DataType::from_name(name).ok_or_else(|| {
+            let message = format!("`{}` expects a type name", self.mnemonic);
+            self.error(message)
+        })
+    }
+
+    /// Small immediate operand, such as the number of values to `FREE`.
+    fn count(&self, arg: Arg) -> AsmResult<u8> {
+        match arg {
+            Arg::Const(Data::Int(n @ 0..=255)) => Ok(n as u8),
+            _ => {
+                let message = format!("`{}` expects an integer between 0 and 255", self.mnemonic);
+                Err(self.error(message))
+            }
+        }
+    }
+
+    fn target(&self, position: usize, labels: &HashMap<String, usize>) -> AsmResult<Arg> {
+        if position == 0 {
+            self.arity(1)?;
+        }
+        match &self.operands[position] {
+            Operand::Arg(arg) => Ok(arg.clone()),
+            Operand::Label(label, column) => match labels.contains_key(label) {
+                true => Ok(Arg::Label(Box::new(label.clone()))),
+                false => Err(AsmError {
+                    line: self.line,
+                    column: *column,
+                    message: format!("undefined label `{}`", label),
+                }),
+            },
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> AsmError {
+        AsmError {
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+}
+
+// ------------------------
+// MARK: PARSER
+//------------------------
+
+impl<'a> Cursor<'a> {
+    fn new(line: usize, source: &'a str) -> Self {
+        Cursor {
+            line,
+            source,
+            pos: 0,
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let next = self.peek()?;
+        self.pos += next.len_utf8();
+        Some(next)
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.pos += expected.len_utf8();
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, expected: char) -> AsmResult<()> {
+        if !self.eat(expected) {
+            return Err(self.error(format!("expected `{}`", expected)));
+        }
+        Ok(())
+    }
+
+    fn column(&self) -> usize {
+        self.source[..self.pos].chars().count() + 1
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    fn is_line_end(&self) -> bool {
+        matches!(self.peek(), None | Some(';'))
+    }
+
+    fn error(&self, message: impl Into<String>) -> AsmError {
+        self.error_at(self.column(), message)
+    }
+
+    fn error_at(&self, column: usize, message: impl Into<String>) -> AsmError {
+        AsmError {
+            line: self.line,
+            column,
+            message: message.into(),
+        }
+    }
+
+    fn word(&mut self) -> String {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '.')
+        {
+            self.bump();
+        }
+        self.source[start..self.pos].to_string()
+    }
+
+    /// Consumes a `name:` label definition if the line starts with one.
+    fn label(&mut self) -> AsmResult<Option<String>> {
+        let start = self.pos;
+        let column = self.column();
+        let word = self.word();
+        if !word.is_empty() && self.eat(':') {
+            if !is_identifier(&word) {
+                return Err(self.error_at(column, format!("invalid label `{}`", word)));
+            }
+            return Ok(Some(word));
+        }
+        self.pos = start;
+        Ok(None)
+    }
+
+    fn operands(&mut self) -> AsmResult<Vec<Operand>> {
+        let mut operands = vec![];
+        self.skip_whitespace();
+        if self.is_line_end() {
+            return Ok(operands);
+        }
+
+        loop {
+            operands.push(self.operand()?);
+            self.skip_whitespace();
+            if self.is_line_end() {
+                return Ok(operands);
+            }
+            self.expect(',')?;
+            self.skip_whitespace();
+        }
+    }
+
+    fn operand(&mut self) -> AsmResult<Operand> {
+        let column = self.column();
+        if self.eat('$') {
+            return Ok(Operand::Arg(Arg::Ref(self.unsigned()?)));
+        }
+        if self.eat('#') {
+            return Ok(Operand::Arg(Arg::ConstIdx(self.unsigned()?)));
+        }
+
+        let start = self.pos;
+        let word = self.word();
+        if word.eq_ignore_ascii_case("acc") {
+            return Ok(Operand::Arg(Arg::Acc));
+        }
+        if is_identifier(&word) && !is_keyword(&word) && self.peek() != Some('"') {
+            return Ok(Operand::Label(word, column));
+        }
+
+        self.pos = start;
+        Ok(Operand::Arg(Arg::Const(self.data()?)))
+    }
+
+    fn data(&mut self) -> AsmResult<Data> {
+        self.skip_whitespace();
+        let column = self.column();
+        let rest = self.rest();
+
+        if rest.starts_with('"') {
+            return Ok(Data::String(Box::new(self.string()?)));
+        }
+        if rest.starts_with("x\"") {
+            self.bump();
+            return Ok(Data::ByteArray(Box::new(self.bytes()?.into_boxed_slice())));
+        }
+        if rest.starts_with("fn\"") {
+            self.pos += 2;
+            return Ok(Data::Function(Box::new(self.string()?)));
+        }
+        if self.eat('@') {
+            return Ok(Data::Pointer(self.unsigned()?));
+        }
+        if self.eat('(') {
+            let items = self.sequence(')')?;
+            return Ok(Data::Tuple(Box::new(items.into_boxed_slice())));
+        }
+        if self.eat('[') {
+            return Ok(Data::List(Box::new(self.sequence(']')?)));
+        }
+        if self.eat('{') {
+            return Ok(Data::Dict(Box::new(self.entries()?)));
+        }
+
+        let word = self.number_word();
+        match word.to_ascii_lowercase().as_str() {
+            "" => Err(self.error_at(column, "expected a value")),
+            "true" => Ok(Data::Bool(true)),
+            "false" => Ok(Data::Bool(false)),
+            "none" => Ok(Data::None),
+            "nan" => Ok(Data::Float(f64::NAN)),
+            "inf" | "+inf" => Ok(Data::Float(f64::INFINITY)),
+            "-inf" => Ok(Data::Float(f64::NEG_INFINITY)),
+            lower => match parse_number(lower) {
+                Some(data) => Ok(data),
+                None if is_integer(lower) => {
+                    Err(self.error_at(column, format!("integer literal `{}` out of range", word)))
+                }
+                None => Err(self.error_at(column, format!("invalid literal `{}`", word))),
+            },
+        }
+    }
+
+    fn number_word(&mut self) -> String {
+        let start = self.pos;
+        if matches!(self.peek(), Some('-' | '+')) {
+            self.bump();
+        }
+        while let Some(c) = self.peek() {
+            let is_exponent_sign =
+                matches!(c, '-' | '+') && self.source[start..self.pos].ends_with(['e', 'E']);
+            if !(c.is_alphanumeric() || c == '.' || c == '_' || is_exponent_sign) {
+                break;
+            }
+            self.bump();
+        }
+        self.source[start..self.pos].to_string()
+    }
+
+    fn unsigned<T: std::str::FromStr>(&mut self) -> AsmResult<T> {
+        let column = self.column();
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.bump();
+        }
+        self.source[start..self.pos]
+            .parse()
+            .map_err(|_| self.error_at(column, "expected an unsigned integer"))
+    }
+
+    fn sequence(&mut self, close: char) -> AsmResult<Vec<Data>> {
+        let mut items = vec![];
+        loop {
+            self.skip_whitespace();
+            if self.eat(close) {
+                return Ok(items);
+            }
+            items.push(self.data()?);
+            self.skip_whitespace();
+            if !self.eat(',') {
+                self.expect(close)?;
+                return Ok(items);
+            }
+        }
+    }
+
+    fn entries(&mut self) -> AsmResult<BTreeMap<Data, Data>> {
+        let mut entries = BTreeMap::new();
+        loop {
+            self.skip_whitespace();
+            if self.eat('}') {
+                return Ok(entries);
+            }
+            let column = self.column();
+            let key = self.data()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            self.skip_whitespace();
+            let value = self.data()?;
+            if entries.insert(key, value).is_some() {
+                return Err(self.error_at(column, "duplicate dictionary key"));
+            }
+            self.skip_whitespace();
+            if !self.eat(',') {
+                self.expect('}')?;
+                return Ok(entries);
+            }
+        }
+    }
+
+    fn string(&mut self) -> AsmResult<String> {
+        let start = self.column();
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            let column = self.column();
+            match self.bump() {
+                None => return Err(self.error_at(start, "unterminated string")),
+                Some('"') => return Ok(value),
+                Some('\\') => value.push(self.escape(column)?),
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    fn escape(&mut self, column: usize) -> AsmResult<char> {
+        let escaped = match self.bump() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some('\\') => '\\',
+            Some('"') => '"',
+            Some('\'') => '\'',
+            Some('u') => {
+                self.expect('{')?;
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+                    self.bump();
+                }
+                let code = u32::from_str_radix(&self.source[start..self.pos], 16).ok();
+                self.expect('}')?;
+                code.and_then(char::from_u32)
+                    .ok_or_else(|| self.error_at(column, "invalid unicode escape"))?
+            }
+            _ => return Err(self.error_at(column, "invalid escape sequence")),
+        };
+        Ok(escaped)
+    }
+
+    fn bytes(&mut self) -> AsmResult<Vec<u8>> {
+        let column = self.column();
+        let digits = self.string()?;
+        if digits.len() % 2 != 0 {
+            return Err(self.error_at(column, "byte array needs an even number of hex digits"));
+        }
+        (0..digits.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&digits[i..i + 2], 16))
+            .collect::<Result<_, _>>()
+            .map_err(|_| self.error_at(column, "invalid hex digit in byte array"))
+    }
+}
+
+fn is_identifier(word: &str) -> bool {
+    let mut chars = word.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+fn is_keyword(word: &str) -> bool {
+    matches!(
+        word.to_ascii_lowercase().as_str(),
+        "acc" | "true" | "false" | "none" | "nan" | "inf"
+    )
+}
+
+fn parse_number(word: &str) -> Option<Data> {
+    let word = word.replace('_', "");
+    if let Some(byte) = word.strip_suffix("u8") {
+        return byte.parse().ok().map(Data::Byte);
+    }
+    // Integers never fall back to Float, only a fraction or an exponent makes one
+    if is_integer(&word) {
+        return word.parse().ok().map(Data::Int);
+    }
+    word.parse().ok().map(Data::Float)
+}
+
+/// Digits with an optional sign, e.g. `-42` or `1_000`.
+fn is_integer(word: &str) -> bool {
+    let digits = word.strip_prefix(['-', '+']).unwrap_or(word);
+    !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit() || c == '_')
+}
+
+// ------------------------
+// MARK: IMPLEMENTS
+//------------------------
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
@@ -0,0 +1,268 @@
+use vm_lib::{ErrorKind, ExecError, ProcessContext};
+
+use crate::{
+    data_types::{Arg, Data},
+    instructions::Instruction,
+};
+
+type OpProc = ProcessContext<Data>;
+type OpResult<T = ()> = Result<T, ExecError<Data>>;
+
+// ------------------------
+// MARK: TYPES
+//------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CollectionKind {
+    List,
+    Tuple,
+    // Built from key/value pairs
+    Dict,
+}
+
+// ------------------------
+// MARK: IMPLEMENTS
+//------------------------
+
+impl CollectionKind {
+    pub const fn mnemonic(&self) -> &'static str {
+        match self {
+            CollectionKind::List => "LIST",
+            CollectionKind::Tuple => "TUPLE",
+            CollectionKind::Dict => "DICT",
+        }
+    }
+
+    pub fn from_mnemonic(name: &str) -> Option<Self> {
+        let kind = match name {
+            "LIST" => CollectionKind::List,
+            "TUPLE" => CollectionKind::Tuple,
+            "DICT" => CollectionKind::Dict,
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    /// Stack values consumed when building `count` items.
+    pub const fn values(&self, count: u8) -> usize {
+        match self {
+            CollectionKind::List | CollectionKind::Tuple => count as usize,
+            CollectionKind::Dict => count as usize * 2,
+        }
+    }
+}
+
+impl Instruction {
+    /// Collects the top stored values into the accumulator, oldest first.
+    pub(crate) fn build(proc: &mut OpProc, kind: CollectionKind, count: u8) -> OpResult {
+        let values = proc.stack.drain(kind.values(count))?;
+
+        let collection = match kind {
+            CollectionKind::List => Data::List(Box::new(values)),
+            CollectionKind::Tuple => Data::Tuple(Box::new(values.into_boxed_slice())),
+            CollectionKind::Dict => {
+                let mut values = values.into_iter();
+                let mut entries = std::collections::BTreeMap::new();
+                while let (Some(key), Some(value)) = (values.next(), values.next()) {
+                    entries.insert(key, value);
+                }
+                Data::Dict(Box::new(entries))
+            }
+        };
+        proc.stack.to_register(collection);
+        Ok(())
+    }
+
+    pub(crate) fn index(proc: &mut OpProc, collection: &Arg, key: &Arg) -> OpResult {
+        let collection = collection.deref(proc)?;
+        let key = key.deref(proc)?;
+
+        let value = match (collection, key) {
+            (Data::List(items), Data::Int(index)) => Self::item(items, collection, *index)?.clone(),
+            (Data::Tuple(items), Data::Int(index)) => {
+                Self::item(items, collection, *index)?.clone()
+            }
+            (Data::ByteArray(bytes), Data::Int(index)) => {
+                Data::Byte(*Self::item(bytes, collection, *index)?)
+            }
+            (Data::Dict(entries), key) => entries
+                .get(key)
+                .ok_or_else(|| Self::missing(collection, key))?
+                .clone(),
+            _ => return Err(Self::mismatch(collection, key)),
+        };
+
+        proc.stack.to_register(value);
+        Ok(())
+    }
+
+    /// Overwrites a list item or inserts a dictionary entry in place.
+    pub(crate) fn set(proc: &mut OpProc, target: &Arg, key: &Arg, value: &Arg) -> OpResult {
+        let key = key.deref(proc)?.clone();
+        let value = value.deref(proc)?.clone();
+        let collection = Self::location(proc, target)?;
+
+        match (&mut *collection, &key) {
+            (Data::List(items), Data::Int(index)) => {
+                let Some(slot) = usize::try_from(*index).ok().and_then(|i| items.get_mut(i)) else {
+                    return Err(Self::out_of_bounds(collection, &key));
+                };
+                *slot = value;
+            }
+            (Data::Dict(entries), _) => {
+                entries.insert(key, value);
+            }
+            _ => return Err(Self::mismatch(collection, &key)),
+        }
+        Ok(())
+    }
+
+    pub(crate) fn append(proc: &mut OpProc, target: &Arg, value: &Arg) -> OpResult {
+        let value = value.deref(proc)?.clone();
+        let collection = Self::location(proc, target)?;
+
+        match collection {
+            Data::List(items) => items.push(value),
+            _ => return Err(Self::mismatch(collection, &value)),
+        }
+        Ok(())
+    }
+
+    /// Takes the last item of a list into the accumulator.
+    pub(crate) fn pop(proc: &mut OpProc, target: &Arg) -> OpResult {
+        let collection = Self::location(proc, target)?;
+
+        let value = match collection {
+            Data::List(items) => match items.pop() {
+                Some(value) => value,
+                None => {
+                    let operands = vec![collection.clone()];
+                    return Err(ExecError::new(ErrorKind::IndexOutOfBounds, operands));
+                }
+            },
+            _ => return Err(Self::not_a_collection(collection)),
+        };
+
+        proc.stack.to_register(value);
+        Ok(())
+    }
+
+    /// Takes a list item or dictionary entry into the accumulator.
+    pub(crate) fn remove(proc: &mut OpProc, target: &Arg, key: &Arg) -> OpResult {
+        let key = key.deref(proc)?.clone();
+        let collection = Self::location(proc, target)?;
+
+        let value = match (&mut *collection, &key) {
+            (Data::List(items), Data::Int(index)) => {
+                match usize::try_from(*index).ok().filter(|i| *i < items.len()) {
+                    Some(index) => items.remove(index),
+                    None => return Err(Self::out_of_bounds(collection, &key)),
+                }
+            }
+            (Data::Dict(entries), _) => match entries.remove(&key) {
+                Some(value) => value,
+                None => return Err(Self::missing(collection, &key)),
+            },
+            _ => return Err(Self::mismatch(collection, &key)),
+        };
+
+        proc.stack.to_register(value);
+        Ok(())
+    }
+
+    /// Items of lists and tuples, keys of dictionaries.
+    pub(crate) fn contains(proc: &mut OpProc, collection: &Arg, value: &Arg) -> OpResult {
+        let collection = collection.deref(proc)?;
+        let value = value.deref(proc)?;
+
+        let found = match collection {
+            Data::List(items) => items.contains(value),
+            Data::Tuple(items) => items.contains(value),
+            Data::Dict(entries) => entries.contains_key(value),
+            _ => return Err(Self::mismatch(collection, value)),
+        };
+
+        proc.stack.to_register(Data::Bool(found));
+        Ok(())
+    }
+
+    pub(crate) fn len(proc: &mut OpProc, collection: &Arg) -> OpResult {
+        let collection = collection.deref(proc)?;
+
+        let len = match collection {
+            Data::List(items) => items.len(),
+            Data::Tuple(items) => items.len(),
+            Data::Dict(entries) => entries.len(),
+            Data::ByteArray(bytes) => bytes.len(),
+            Data::String(value) => value.chars().count(),
+            _ => return Err(Self::not_a_collection(collection)),
+        };
+
+        proc.stack.to_register(Data::Int(len as i64));
+        Ok(())
+    }
+
+    /// Stores every item of a tuple or list, which must hold exactly `count` of them.
+    pub(crate) fn unpack(proc: &mut OpProc, collection: &Arg, count: u8) -> OpResult {
+        let collection = collection.deref(proc)?;
+
+        let items = match collection {
+            Data::Tuple(items) => items.to_vec(),
+            Data::List(items) => items.to_vec(),
+            _ => return Err(Self::not_a_collection(collection)),
+        };
+        if items.len() != count as usize {
+            let operands = vec![collection.clone(), Data::Int(count as i64)];
+            return Err(ExecError::new(ErrorKind::LengthMismatch, operands));
+        }
+
+        for item in items {
+            proc.stack.to_register(item);
+            proc.stack.store_register()?;
+        }
+        proc.stack.to_register(Data::None);
+        Ok(())
+    }
+
+    /// Stack slot or accumulator a collection is modified in.
+    fn location<'a>(proc: &'a mut OpProc, target: &Arg) -> OpResult<&'a mut Data> {
+        match target {
+            Arg::Ref(name) => Ok(proc.stack.peek_register_mut(*name + 1)?),
+            Arg::Acc => Ok(proc.stack.peek_register_mut(0)?),
+            Arg::Const(_) | Arg::ConstIdx(_) | Arg::Label(_) => {
+                let operands = vec![target.deref(proc)?.clone()];
+                Err(ExecError::new(ErrorKind::InvalidTarget, operands))
+            }
+        }
+    }
+
+    fn item<'a, T>(items: &'a [T], collection: &Data, index: i64) -> OpResult<&'a T> {
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| items.get(index))
+            .ok_or_else(|| Self::out_of_bounds(collection, &Data::Int(index)))
+    }
+
+    fn out_of_bounds(collection: &Data, index: &Data) -> ExecError<Data> {
+        let operands = vec![collection.clone(), index.clone()];
+        ExecError::new(ErrorKind::IndexOutOfBounds, operands)
+    }
+
+    fn missing(collection: &Data, key: &Data) -> ExecError<Data> {
+        ExecError::new(
+            ErrorKind::KeyNotFound,
+            vec![collection.clone(), key.clone()],
+        )
+    }
+
+    fn not_a_collection(collection: &Data) -> ExecError<Data> {
+        ExecError::new(ErrorKind::TypeMismatch, vec![collection.clone()])
+    }
+
+    fn mismatch(collection: &Data, other: &Data) -> ExecError<Data> {
+        ExecError::new(
+            ErrorKind::TypeMismatch,
+            vec![collection.clone(), other.clone()],
+        )
+    }
+}
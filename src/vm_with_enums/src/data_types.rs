@@ -1,8 +1,19 @@
-use std::collections::BTreeMap;
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    fmt::{self, Display},
+    hash::{Hash, Hasher},
+};
 
-use vm_lib::{NativeType, Stack};
+use vm_lib::{ConstantPool, ErrorKind, ExecError, NativeType, ProcessContext};
 
-#[derive(Debug, Clone, Default, PartialEq)]
+/// Values are totally ordered so they can be used as `Dict` keys:
+/// - values of the same variant compare by content, collections element by element;
+/// - `Float` follows `f64::total_cmp` with `-0.0` read as `0.0` and every `NaN` as
+///   `f64::NAN`, so `NaN` equals itself and sorts above every other float, and `-0.0`
+///   equals `0.0`;
+/// - values of different variants compare by variant, in declaration order.
+#[derive(Debug, Clone, Default)]
 pub enum Data {
     Int(i64),
     Float(f64),
@@ -13,34 +24,267 @@ pub enum Data {
     Tuple(Box<Box<[Data]>>),
     List(Box<Vec<Data>>),
     Dict(Box<BTreeMap<Data, Data>>),
-    Pointer(usize),
+    Pointer(u64),
     Function(Box<String>),
 
     #[default]
     None,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub enum Arg {
     Const(Data),
+    //Entry of the constant pool
+    ConstIdx(usize),
+    //Value `n` slots below the top of the Stack (`Ref(0)` is the last stored value),
+    //the same slot whether an instruction reads it, copies into it, loads or stores it
     Ref(usize),
+    #[default]
     Acc,
+    //Named jump target, resolved by `ProgramCode::compile`
+    Label(Box<String>),
+}
+
+impl NativeType for Data {
+    fn trace(&self, mark: &mut dyn FnMut(u64)) {
+        match self {
+            Data::Pointer(bits) => mark(*bits),
+            Data::Tuple(items) => items.iter().for_each(|item| item.trace(mark)),
+            Data::List(items) => items.iter().for_each(|item| item.trace(mark)),
+            Data::Dict(items) => items.iter().for_each(|(key, value)| {
+                key.trace(mark);
+                value.trace(mark);
+            }),
+            _ => {}
+        }
+    }
+
+    fn size(&self) -> usize {
+        let boxed = match self {
+            Data::ByteArray(bytes) => size_of::<Box<[u8]>>() + bytes.len(),
+            Data::String(text) | Data::Function(text) => size_of::<String>() + text.capacity(),
+            Data::Tuple(items) => size_of::<Box<[Data]>>() + Self::items_size(items.iter()),
+            Data::List(items) => {
+                let spare = (items.capacity() - items.len()) * size_of::<Data>();
+                size_of::<Vec<Data>>() + spare + Self::items_size(items.iter())
+            }
+            Data::Dict(items) => {
+                let entries = items.iter().flat_map(|(key, value)| [key, value]);
+                size_of::<BTreeMap<Data, Data>>() + Self::items_size(entries)
+            }
+            _ => 0,
+        };
+        size_of::<Data>() + boxed
+    }
+}
+
+impl Data {
+    /// `-0.0` as `0.0` and any `NaN`, whatever its sign and payload, as `f64::NAN`.
+    const fn float_key(value: f64) -> f64 {
+        if value.is_nan() {
+            f64::NAN
+        } else if value == 0.0 {
+            0.0
+        } else {
+            value
+        }
+    }
+
+    fn items_size<'a>(items: impl Iterator<Item = &'a Data>) -> usize {
+        items.map(NativeType::size).sum()
+    }
+
+    /// Position of the variant in the order between different types.
+    pub const fn rank(&self) -> u8 {
+        match self {
+            Data::Int(_) => 0,
+            Data::Float(_) => 1,
+            Data::Bool(_) => 2,
+            Data::Byte(_) => 3,
+            Data::ByteArray(_) => 4,
+            Data::String(_) => 5,
+            Data::Tuple(_) => 6,
+            Data::List(_) => 7,
+            Data::Dict(_) => 8,
+            Data::Pointer(_) => 9,
+            Data::Function(_) => 10,
+            Data::None => 11,
+        }
+    }
+
+    /// Values cheap enough to stay inline instead of going to the constant pool.
+    pub const fn is_scalar(&self) -> bool {
+        matches!(
+            self,
+            Data::Int(_)
+                | Data::Float(_)
+                | Data::Bool(_)
+                | Data::Byte(_)
+                | Data::Pointer(_)
+                | Data::None
+        )
+    }
+}
+
+impl Ord for Data {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Data::Int(a), Data::Int(b)) => a.cmp(b),
+            (Data::Float(a), Data::Float(b)) => Self::float_key(*a).total_cmp(&Self::float_key(*b)),
+            (Data::Bool(a), Data::Bool(b)) => a.cmp(b),
+            (Data::Byte(a), Data::Byte(b)) => a.cmp(b),
+            (Data::ByteArray(a), Data::ByteArray(b)) => a.cmp(b),
+            (Data::String(a), Data::String(b)) => a.cmp(b),
+            (Data::Tuple(a), Data::Tuple(b)) => a.cmp(b),
+            (Data::List(a), Data::List(b)) => a.cmp(b),
+            (Data::Dict(a), Data::Dict(b)) => a.cmp(b),
+            (Data::Pointer(a), Data::Pointer(b)) => a.cmp(b),
+            (Data::Function(a), Data::Function(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl PartialOrd for Data {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Data {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
 }
 
-impl NativeType for Data {}
+impl Eq for Data {}
 
-impl Default for Arg {
-    fn default() -> Self {
-        Arg::Acc
+impl Hash for Data {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.rank().hash(state);
+        match self {
+            Data::Int(value) => value.hash(state),
+            // Equal floats have the same bits once `-0.0` is read as `0.0`
+            Data::Float(value) => Self::float_key(*value).to_bits().hash(state),
+            Data::Bool(value) => value.hash(state),
+            Data::Byte(value) => value.hash(state),
+            Data::ByteArray(bytes) => bytes.hash(state),
+            Data::String(value) => value.hash(state),
+            Data::Tuple(items) => items.hash(state),
+            Data::List(items) => items.hash(state),
+            Data::Dict(items) => items.hash(state),
+            Data::Pointer(value) => value.hash(state),
+            Data::Function(name) => name.hash(state),
+            Data::None => {}
+        }
     }
 }
+
 impl Arg {
     #[inline]
-    pub fn deref<'a>(&'a self, stack: &'a Stack<Data>) -> &'a Data {
-        match self {
+    pub fn deref<'a>(
+        &'a self,
+        proc: &'a ProcessContext<Data>,
+    ) -> Result<&'a Data, ExecError<Data>> {
+        let value = match self {
             Arg::Const(data) => data,
-            Arg::Ref(name) => stack.peek_register((*name + 1) as usize),
-            Arg::Acc => stack.peek_register(0),
+            Arg::ConstIdx(index) => proc.get_constant(*index)?,
+            Arg::Ref(name) => proc.stack.peek_register(*name + 1)?,
+            Arg::Acc => proc.stack.peek_register(0)?,
+            Arg::Label(label) => {
+                let operands = vec![Data::String(label.clone())];
+                return Err(ExecError::new(ErrorKind::UnresolvedLabel, operands));
+            }
+        };
+        Ok(value)
+    }
+
+    /// Value of a constant operand, known without running the program.
+    pub fn constant<'a>(&'a self, constants: &'a [Data]) -> Option<&'a Data> {
+        match self {
+            Arg::Const(data) => Some(data),
+            Arg::ConstIdx(index) => constants.get(*index),
+            Arg::Ref(_) | Arg::Acc | Arg::Label(_) => None,
+        }
+    }
+
+    /// Stack slots below the top that reading this operand needs.
+    pub const fn depth(&self) -> usize {
+        match self {
+            Arg::Ref(name) => *name + 1,
+            Arg::Const(_) | Arg::ConstIdx(_) | Arg::Acc | Arg::Label(_) => 0,
+        }
+    }
+
+    pub fn hoist(&mut self, pool: &mut ConstantPool<Data>) {
+        if let Arg::Const(data) = self
+            && !data.is_scalar()
+        {
+            let index = pool.intern(std::mem::take(data));
+            *self = Arg::ConstIdx(index);
+        }
+    }
+}
+
+/// Renders values with the literal syntax understood by the assembler.
+impl Display for Data {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Data::Int(value) => write!(f, "{}", value),
+            Data::Float(value) => write!(f, "{:?}", value),
+            Data::Bool(value) => write!(f, "{}", value),
+            Data::Byte(value) => write!(f, "{}u8", value),
+            Data::ByteArray(bytes) => {
+                f.write_str("x\"")?;
+                for byte in bytes.iter() {
+                    write!(f, "{:02x}", byte)?;
+                }
+                f.write_str("\"")
+            }
+            Data::String(value) => write!(f, "{:?}", value),
+            Data::Tuple(items) => write_sequence(f, "(", items.iter(), ")"),
+            Data::List(items) => write_sequence(f, "[", items.iter(), "]"),
+            Data::Dict(items) => {
+                f.write_str("{")?;
+                for (index, (key, value)) in items.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}: {}", key, value)?;
+                }
+                f.write_str("}")
+            }
+            Data::Pointer(value) => write!(f, "@{}", value),
+            Data::Function(name) => write!(f, "fn{:?}", name),
+            Data::None => f.write_str("none"),
+        }
+    }
+}
+
+impl Display for Arg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arg::Const(data) => write!(f, "{}", data),
+            Arg::ConstIdx(index) => write!(f, "#{}", index),
+            Arg::Ref(name) => write!(f, "${}", name),
+            Arg::Acc => f.write_str("acc"),
+            Arg::Label(label) => f.write_str(label),
+        }
+    }
+}
+
+fn write_sequence<'a>(
+    f: &mut fmt::Formatter<'_>,
+    open: &str,
+    items: impl Iterator<Item = &'a Data>,
+    close: &str,
+) -> fmt::Result {
+    f.write_str(open)?;
+    for (index, item) in items.enumerate() {
+        if index > 0 {
+            f.write_str(", ")?;
         }
+        write!(f, "{}", item)?;
     }
+    f.write_str(close)
 }
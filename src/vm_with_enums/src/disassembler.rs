@@ -0,0 +1,160 @@
+//! Renders compiled [`ByteCode`] back into the assembly accepted by [`assemble`].
+//!
+//! Every instruction is tagged with its index, and jump targets that land inside the
+//! program are written as labels so relative offsets survive a round trip. Label names
+//! come from the debug symbols when present, or are generated as `L<index>`.
+//!
+//! [`assemble`]: crate::assembler::assemble
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use vm_lib::ByteCode;
+
+use crate::{
+    data_types::{Arg, Data},
+    instructions::Instruction,
+};
+
+type LabelNames = BTreeMap<usize, Vec<String>>;
+
+pub fn disassemble(bytecode: &ByteCode<Instruction, Data>) -> String {
+    let instructions = bytecode.get();
+
+    let mut labels = LabelNames::new();
+    if let Some(debug) = bytecode.get_debug() {
+        for (index, name) in &debug.symbols {
+            labels.entry(*index).or_default().push(name.clone());
+        }
+    }
+    for (index, op) in instructions.iter().enumerate() {
+        if let Some(target) = jump_target(index, op, instructions.len()) {
+            labels
+                .entry(target)
+                .or_insert_with(|| vec![format!("L{:04}", target)]);
+        }
+    }
+
+    let mut output = String::new();
+    if !bytecode.get_constants().is_empty() {
+        output.push_str("; constants\n");
+        for (index, constant) in bytecode.get_constants().iter().enumerate() {
+            let line = format!(".const {}", constant);
+            let _ = writeln!(output, "{:<32} ; #{}", line, index);
+        }
+        output.push('\n');
+    }
+
+    output.push_str("; code\n");
+    for (index, op) in instructions.iter().enumerate() {
+        let label = match labels.get(&index) {
+            Some(names) => format!("{}:", names.join(": ")),
+            None => String::new(),
+        };
+        // Long labels still need a space before the mnemonic
+        let line = format!(
+            "{:<width$}{}",
+            label,
+            render(index, op, &labels, instructions.len()),
+            width = label.len().max(7) + 1
+        );
+        let _ = write!(output, "{:<40} ; {:04}", line, index);
+        if let Some(target) = absolute_target(index, op) {
+            let _ = write!(output, " -> {:04}", target);
+        }
+        output.push('\n');
+    }
+
+    // Labels pointing just past the last instruction still need a definition.
+    for names in labels.range(instructions.len()..).map(|(_, names)| names) {
+        let _ = writeln!(output, "{}:", names.join(": "));
+    }
+
+    output
+}
+
+fn render(index: usize, op: &Instruction, labels: &LabelNames, len: usize) -> String {
+    let target = |arg: &Arg| match jump_target(index, op, len) {
+        Some(target) => labels[&target][0].clone(),
+        None => arg.to_string(),
+    };
+
+    match op {
+        Instruction::BinaryOp(op, a, b) => format!("{} {}, {}", op.mnemonic(), a, b),
+        Instruction::UnaryOp(op, arg) => format!("{} {}", op.mnemonic(), arg),
+        Instruction::Store(arg) => format!("STORE {}", arg),
+        Instruction::Load(arg) => format!("LOAD {}", arg),
+        Instruction::Copy(src, tgt) => format!("COPY {}, {}", src, tgt),
+        Instruction::Free(n) => format!("FREE {}", n),
+        Instruction::Alloc(arg) => format!("ALLOC {}", arg),
+        Instruction::Dealloc(arg) => format!("DEALLOC {}", arg),
+        Instruction::HeapLoad(arg) => format!("HLOAD {}", arg),
+        Instruction::HeapStore(ptr, arg) => format!("HSTORE {}, {}", ptr, arg),
+        Instruction::Build(kind, count) => format!("{} {}", kind.mnemonic(), count),
+        Instruction::Index(collection, key) => format!("INDEX {}, {}", collection, key),
+        Instruction::Set(target, key, value) => format!("SET {}, {}, {}", target, key, value),
+        Instruction::Append(target, value) => format!("APPEND {}, {}", target, value),
+        Instruction::Pop(target) => format!("POP {}", target),
+        Instruction::Remove(target, key) => format!("REMOVE {}, {}", target, key),
+        Instruction::Contains(collection, value) => {
+            format!("CONTAINS {}, {}", collection, value)
+        }
+        Instruction::Len(collection) => format!("LEN {}", collection),
+        Instruction::Unpack(collection, count) => format!("UNPACK {}, {}", collection, count),
+        Instruction::Slice(text, start, end) => format!("SLICE {}, {}, {}", text, start, end),
+        Instruction::Find(text, needle) => format!("FIND {}, {}", text, needle),
+        Instruction::Replace(text, from, to) => format!("REPLACE {}, {}, {}", text, from, to),
+        Instruction::Split(text, separator) => format!("SPLIT {}, {}", text, separator),
+        Instruction::Join(list, separator) => format!("JOIN {}, {}", list, separator),
+        Instruction::Upper(text) => format!("UPPER {}", text),
+        Instruction::Lower(text) => format!("LOWER {}", text),
+        Instruction::Trim(text) => format!("TRIM {}", text),
+        Instruction::StartsWith(text, prefix) => format!("STARTSWITH {}, {}", text, prefix),
+        Instruction::EndsWith(text, suffix) => format!("ENDSWITH {}, {}", text, suffix),
+        Instruction::Cast(arg, target) => format!("CAST {}, {}", arg, target.name()),
+        Instruction::TypeOf(arg) => format!("TYPEOF {}", arg),
+        Instruction::IsType(arg, expected) => format!("ISTYPE {}, {}", arg, expected.name()),
+        Instruction::Jump(arg) => format!("JUMP {}", target(arg)),
+        Instruction::JumpIf(cond, arg) => format!("JUMPIF {}, {}", cond, target(arg)),
+        Instruction::Call(arg) => format!("CALL {}", target(arg)),
+        Instruction::CallNative(function, count) => format!("CALLNATIVE {}, {}", function, count),
+        Instruction::Return => "RETURN".to_string(),
+        Instruction::Print(arg) => format!("PRINT {}", arg),
+        Instruction::ReadLine => "READLINE".to_string(),
+        Instruction::ReadBytes(count) => format!("READBYTES {}", count),
+        Instruction::HALT => "HALT".to_string(),
+    }
+}
+
+/// Instruction executed after a taken jump, when it is known statically.
+fn absolute_target(index: usize, op: &Instruction) -> Option<usize> {
+    let (Instruction::Jump(Arg::Const(target))
+    | Instruction::JumpIf(_, Arg::Const(target))
+    | Instruction::Call(Arg::Const(target))) = op
+    else {
+        return None;
+    };
+
+    let target = match target {
+        Data::Byte(ipointer) => (*ipointer as usize).checked_add(1)?,
+        Data::Pointer(ipointer) => usize::try_from(*ipointer).ok()?.checked_add(1)?,
+        Data::Int(offset) => {
+            let target = (index as i64).checked_add(*offset)?.checked_add(1)?;
+            usize::try_from(target).ok()?
+        }
+        Data::Bool(offset) => index + *offset as usize + 1,
+        _ => return None,
+    };
+    Some(target)
+}
+
+/// Relative jump target that can be written back as a label.
+fn jump_target(index: usize, op: &Instruction, len: usize) -> Option<usize> {
+    match op {
+        Instruction::Jump(Arg::Const(Data::Int(_)))
+        | Instruction::JumpIf(_, Arg::Const(Data::Int(_)))
+        | Instruction::Call(Arg::Const(Data::Int(_))) => {
+            absolute_target(index, op).filter(|target| *target <= len)
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,538 @@
+use vm_lib::{FormatError, Reader, Serializable, Writer};
+
+use crate::{
+    collections::CollectionKind,
+    data_types::{Arg, Data},
+    instructions::{BinaryOp, Instruction, UnaryOp},
+    types::DataType,
+};
+
+// ------------------------
+// MARK: DATA
+//------------------------
+
+impl Serializable for Data {
+    fn encode(&self, out: &mut Writer) {
+        match self {
+            Data::Int(value) => {
+                out.u8(0);
+                out.u64(*value as u64);
+            }
+            Data::Float(value) => {
+                out.u8(1);
+                out.u64(value.to_bits());
+            }
+            Data::Bool(value) => {
+                out.u8(2);
+                out.u8(*value as u8);
+            }
+            Data::Byte(value) => {
+                out.u8(3);
+                out.u8(*value);
+            }
+            Data::ByteArray(bytes) => {
+                out.u8(4);
+                out.bytes(bytes);
+            }
+            Data::String(value) => {
+                out.u8(5);
+                out.str(value);
+            }
+            Data::Tuple(items) => {
+                out.u8(6);
+                out.items(items);
+            }
+            Data::List(items) => {
+                out.u8(7);
+                out.items(items);
+            }
+            Data::Dict(items) => {
+                out.u8(8);
+                out.u32(items.len() as u32);
+                for (key, value) in items.iter() {
+                    key.encode(out);
+                    value.encode(out);
+                }
+            }
+            Data::Pointer(value) => {
+                out.u8(9);
+                out.u64(*value);
+            }
+            Data::Function(name) => {
+                out.u8(10);
+                out.str(name);
+            }
+            Data::None => out.u8(11),
+        }
+    }
+
+    fn decode(input: &mut Reader) -> Result<Self, FormatError> {
+        let data = match input.u8()? {
+            0 => Data::Int(input.u64()? as i64),
+            1 => Data::Float(f64::from_bits(input.u64()?)),
+            2 => Data::Bool(input.u8()? != 0),
+            3 => Data::Byte(input.u8()?),
+            4 => Data::ByteArray(Box::new(input.bytes()?.into())),
+            5 => Data::String(Box::new(input.string()?)),
+            6 => Data::Tuple(Box::new(input.nested(Reader::items)?.into_boxed_slice())),
+            7 => Data::List(Box::new(input.nested(Reader::items)?)),
+            8 => input.nested(|input| {
+                let len = input.u32()?;
+                let entries = (0..len)
+                    .map(|_| Ok((Data::decode(input)?, Data::decode(input)?)))
+                    .collect::<Result<_, FormatError>>()?;
+                Ok(Data::Dict(Box::new(entries)))
+            })?,
+            9 => Data::Pointer(input.u64()?),
+            10 => Data::Function(Box::new(input.string()?)),
+            11 => Data::None,
+            tag => return Err(FormatError::InvalidTag { kind: "data", tag }),
+        };
+        Ok(data)
+    }
+}
+
+impl Serializable for Arg {
+    fn encode(&self, out: &mut Writer) {
+        match self {
+            Arg::Const(data) => {
+                out.u8(0);
+                data.encode(out);
+            }
+            Arg::Ref(name) => {
+                out.u8(1);
+                out.usize(*name);
+            }
+            Arg::Acc => out.u8(2),
+            Arg::ConstIdx(index) => {
+                out.u8(3);
+                out.usize(*index);
+            }
+            Arg::Label(label) => out.fail(FormatError::UnresolvedLabel(label.to_string())),
+        }
+    }
+
+    fn decode(input: &mut Reader) -> Result<Self, FormatError> {
+        let arg = match input.u8()? {
+            0 => Arg::Const(Data::decode(input)?),
+            1 => Arg::Ref(input.usize()?),
+            2 => Arg::Acc,
+            3 => Arg::ConstIdx(input.usize()?),
+            tag => {
+                return Err(FormatError::InvalidTag {
+                    kind: "argument",
+                    tag,
+                });
+            }
+        };
+        Ok(arg)
+    }
+}
+
+// ------------------------
+// MARK: INSTRUCTIONS
+//------------------------
+
+impl Serializable for BinaryOp {
+    fn encode(&self, out: &mut Writer) {
+        let tag = match self {
+            BinaryOp::Add => 0,
+            BinaryOp::Subtract => 1,
+            BinaryOp::Multiply => 2,
+            BinaryOp::Divide => 3,
+            BinaryOp::GT => 4,
+            BinaryOp::GET => 5,
+            BinaryOp::LT => 6,
+            BinaryOp::LET => 7,
+            BinaryOp::EQ => 8,
+            BinaryOp::NEQ => 9,
+            BinaryOp::Mod => 10,
+            BinaryOp::Pow => 11,
+            BinaryOp::And => 12,
+            BinaryOp::Or => 13,
+            BinaryOp::Xor => 14,
+            BinaryOp::Shl => 15,
+            BinaryOp::Shr => 16,
+        };
+        out.u8(tag);
+    }
+
+    fn decode(input: &mut Reader) -> Result<Self, FormatError> {
+        let op = match input.u8()? {
+            0 => BinaryOp::Add,
+            1 => BinaryOp::Subtract,
+            2 => BinaryOp::Multiply,
+            3 => BinaryOp::Divide,
+            4 => BinaryOp::GT,
+            5 => BinaryOp::GET,
+            6 => BinaryOp::LT,
+            7 => BinaryOp::LET,
+            8 => BinaryOp::EQ,
+            9 => BinaryOp::NEQ,
+            10 => BinaryOp::Mod,
+            11 => BinaryOp::Pow,
+            12 => BinaryOp::And,
+            13 => BinaryOp::Or,
+            14 => BinaryOp::Xor,
+            15 => BinaryOp::Shl,
+            16 => BinaryOp::Shr,
+            tag => {
+                return Err(FormatError::InvalidTag {
+                    kind: "operator",
+                    tag,
+                });
+            }
+        };
+        Ok(op)
+    }
+}
+
+impl Serializable for UnaryOp {
+    fn encode(&self, out: &mut Writer) {
+        let tag = match self {
+            UnaryOp::Neg => 0,
+            UnaryOp::Not => 1,
+            UnaryOp::BitNot => 2,
+            UnaryOp::Abs => 3,
+        };
+        out.u8(tag);
+    }
+
+    fn decode(input: &mut Reader) -> Result<Self, FormatError> {
+        let op = match input.u8()? {
+            0 => UnaryOp::Neg,
+            1 => UnaryOp::Not,
+            2 => UnaryOp::BitNot,
+            3 => UnaryOp::Abs,
+            tag => {
+                return Err(FormatError::InvalidTag {
+                    kind: "unary operator",
+                    tag,
+                });
+            }
+        };
+        Ok(op)
+    }
+}
+
+impl Serializable for CollectionKind {
+    fn encode(&self, out: &mut Writer) {
+        let tag = match self {
+            CollectionKind::List => 0,
+            CollectionKind::Tuple => 1,
+            CollectionKind::Dict => 2,
+        };
+        out.u8(tag);
+    }
+
+    fn decode(input: &mut Reader) -> Result<Self, FormatError> {
+        let kind = match input.u8()? {
+            0 => CollectionKind::List,
+            1 => CollectionKind::Tuple,
+            2 => CollectionKind::Dict,
+            tag => {
+                return Err(FormatError::InvalidTag {
+                    kind: "collection",
+                    tag,
+                });
+            }
+        };
+        Ok(kind)
+    }
+}
+
+impl Serializable for DataType {
+    fn encode(&self, out: &mut Writer) {
+        let tag = match self {
+            DataType::Int => 0,
+            DataType::Float => 1,
+            DataType::Bool => 2,
+            DataType::Byte => 3,
+            DataType::ByteArray => 4,
+            DataType::String => 5,
+            DataType::Tuple => 6,
+            DataType::List => 7,
+            DataType::Dict => 8,
+            DataType::Pointer => 9,
+            DataType::Function => 10,
+            DataType::None => 11,
+        };
+        out.u8(tag);
+    }
+
+    fn decode(input: &mut Reader) -> Result<Self, FormatError> {
+        let kind = match input.u8()? {
+            0 => DataType::Int,
+            1 => DataType::Float,
+            2 => DataType::Bool,
+            3 => DataType::Byte,
+            4 => DataType::ByteArray,
+            5 => DataType::String,
+            6 => DataType::Tuple,
+            7 => DataType::List,
+            8 => DataType::Dict,
+            9 => DataType::Pointer,
+            10 => DataType::Function,
+            11 => DataType::None,
+            tag => return Err(FormatError::InvalidTag { kind: "type", tag }),
+        };
+        Ok(kind)
+    }
+}
+
+impl Serializable for Instruction {
+    fn encode(&self, out: &mut Writer) {
+        match self {
+            Instruction::BinaryOp(op, a, b) => {
+                out.u8(0);
+                op.encode(out);
+                a.encode(out);
+                b.encode(out);
+            }
+            Instruction::Store(arg) => {
+                out.u8(1);
+                arg.encode(out);
+            }
+            Instruction::Load(arg) => {
+                out.u8(2);
+                arg.encode(out);
+            }
+            Instruction::Copy(src, tgt) => {
+                out.u8(3);
+                src.encode(out);
+                tgt.encode(out);
+            }
+            Instruction::Free(n) => {
+                out.u8(4);
+                out.u8(*n);
+            }
+            Instruction::Jump(arg) => {
+                out.u8(5);
+                arg.encode(out);
+            }
+            Instruction::JumpIf(cond, arg) => {
+                out.u8(6);
+                cond.encode(out);
+                arg.encode(out);
+            }
+            Instruction::Call(arg) => {
+                out.u8(7);
+                arg.encode(out);
+            }
+            Instruction::Return => out.u8(8),
+            Instruction::Print(arg) => {
+                out.u8(9);
+                arg.encode(out);
+            }
+            Instruction::HALT => out.u8(10),
+            Instruction::Alloc(arg) => {
+                out.u8(11);
+                arg.encode(out);
+            }
+            Instruction::Dealloc(arg) => {
+                out.u8(12);
+                arg.encode(out);
+            }
+            Instruction::HeapLoad(arg) => {
+                out.u8(13);
+                arg.encode(out);
+            }
+            Instruction::HeapStore(ptr, arg) => {
+                out.u8(14);
+                ptr.encode(out);
+                arg.encode(out);
+            }
+            Instruction::Build(kind, count) => {
+                out.u8(15);
+                kind.encode(out);
+                out.u8(*count);
+            }
+            Instruction::Index(collection, key) => {
+                out.u8(16);
+                collection.encode(out);
+                key.encode(out);
+            }
+            Instruction::Set(target, key, value) => {
+                out.u8(17);
+                target.encode(out);
+                key.encode(out);
+                value.encode(out);
+            }
+            Instruction::Append(target, value) => {
+                out.u8(18);
+                target.encode(out);
+                value.encode(out);
+            }
+            Instruction::Pop(target) => {
+                out.u8(19);
+                target.encode(out);
+            }
+            Instruction::Remove(target, key) => {
+                out.u8(20);
+                target.encode(out);
+                key.encode(out);
+            }
+            Instruction::Contains(collection, value) => {
+                out.u8(21);
+                collection.encode(out);
+                value.encode(out);
+            }
+            Instruction::Len(collection) => {
+                out.u8(22);
+                collection.encode(out);
+            }
+            Instruction::Unpack(collection, count) => {
+                out.u8(23);
+                collection.encode(out);
+                out.u8(*count);
+            }
+            Instruction::Slice(text, start, end) => {
+                out.u8(24);
+                text.encode(out);
+                start.encode(out);
+                end.encode(out);
+            }
+            Instruction::UnaryOp(op, arg) => {
+                out.u8(25);
+                op.encode(out);
+                arg.encode(out);
+            }
+            Instruction::Cast(arg, target) => {
+                out.u8(26);
+                arg.encode(out);
+                target.encode(out);
+            }
+            Instruction::TypeOf(arg) => {
+                out.u8(27);
+                arg.encode(out);
+            }
+            Instruction::IsType(arg, expected) => {
+                out.u8(28);
+                arg.encode(out);
+                expected.encode(out);
+            }
+            Instruction::CallNative(function, count) => {
+                out.u8(29);
+                function.encode(out);
+                out.u8(*count);
+            }
+            Instruction::ReadLine => out.u8(30),
+            Instruction::ReadBytes(count) => {
+                out.u8(31);
+                count.encode(out);
+            }
+            Instruction::Find(text, needle) => {
+                out.u8(32);
+                text.encode(out);
+                needle.encode(out);
+            }
+            Instruction::Replace(text, from, to) => {
+                out.u8(33);
+                text.encode(out);
+                from.encode(out);
+                to.encode(out);
+            }
+            Instruction::Split(text, separator) => {
+                out.u8(34);
+                text.encode(out);
+                separator.encode(out);
+            }
+            Instruction::Join(list, separator) => {
+                out.u8(35);
+                list.encode(out);
+                separator.encode(out);
+            }
+            Instruction::Upper(text) => {
+                out.u8(36);
+                text.encode(out);
+            }
+            Instruction::Lower(text) => {
+                out.u8(37);
+                text.encode(out);
+            }
+            Instruction::Trim(text) => {
+                out.u8(38);
+                text.encode(out);
+            }
+            Instruction::StartsWith(text, prefix) => {
+                out.u8(39);
+                text.encode(out);
+                prefix.encode(out);
+            }
+            Instruction::EndsWith(text, suffix) => {
+                out.u8(40);
+                text.encode(out);
+                suffix.encode(out);
+            }
+        }
+    }
+
+    fn decode(input: &mut Reader) -> Result<Self, FormatError> {
+        let op = match input.u8()? {
+            0 => Instruction::BinaryOp(
+                BinaryOp::decode(input)?,
+                Arg::decode(input)?,
+                Arg::decode(input)?,
+            ),
+            1 => Instruction::Store(Arg::decode(input)?),
+            2 => Instruction::Load(Arg::decode(input)?),
+            3 => Instruction::Copy(Arg::decode(input)?, Arg::decode(input)?),
+            4 => Instruction::Free(input.u8()?),
+            5 => Instruction::Jump(Arg::decode(input)?),
+            6 => Instruction::JumpIf(Arg::decode(input)?, Arg::decode(input)?),
+            7 => Instruction::Call(Arg::decode(input)?),
+            8 => Instruction::Return,
+            9 => Instruction::Print(Arg::decode(input)?),
+            10 => Instruction::HALT,
+            11 => Instruction::Alloc(Arg::decode(input)?),
+            12 => Instruction::Dealloc(Arg::decode(input)?),
+            13 => Instruction::HeapLoad(Arg::decode(input)?),
+            14 => Instruction::HeapStore(Arg::decode(input)?, Arg::decode(input)?),
+            15 => Instruction::Build(CollectionKind::decode(input)?, input.u8()?),
+            16 => Instruction::Index(Arg::decode(input)?, Arg::decode(input)?),
+            17 => Instruction::Set(
+                Arg::decode(input)?,
+                Arg::decode(input)?,
+                Arg::decode(input)?,
+            ),
+            18 => Instruction::Append(Arg::decode(input)?, Arg::decode(input)?),
+            19 => Instruction::Pop(Arg::decode(input)?),
+            20 => Instruction::Remove(Arg::decode(input)?, Arg::decode(input)?),
+            21 => Instruction::Contains(Arg::decode(input)?, Arg::decode(input)?),
+            22 => Instruction::Len(Arg::decode(input)?),
+            23 => Instruction::Unpack(Arg::decode(input)?, input.u8()?),
+            24 => Instruction::Slice(
+                Arg::decode(input)?,
+                Arg::decode(input)?,
+                Arg::decode(input)?,
+            ),
+            25 => Instruction::UnaryOp(UnaryOp::decode(input)?, Arg::decode(input)?),
+            26 => Instruction::Cast(Arg::decode(input)?, DataType::decode(input)?),
+            27 => Instruction::TypeOf(Arg::decode(input)?),
+            28 => Instruction::IsType(Arg::decode(input)?, DataType::decode(input)?),
+            29 => Instruction::CallNative(Arg::decode(input)?, input.u8()?),
+            30 => Instruction::ReadLine,
+            31 => Instruction::ReadBytes(Arg::decode(input)?),
+            32 => Instruction::Find(Arg::decode(input)?, Arg::decode(input)?),
+            33 => Instruction::Replace(
+                Arg::decode(input)?,
+                Arg::decode(input)?,
+                Arg::decode(input)?,
+            ),
+            34 => Instruction::Split(Arg::decode(input)?, Arg::decode(input)?),
+            35 => Instruction::Join(Arg::decode(input)?, Arg::decode(input)?),
+            36 => Instruction::Upper(Arg::decode(input)?),
+            37 => Instruction::Lower(Arg::decode(input)?),
+            38 => Instruction::Trim(Arg::decode(input)?),
+            39 => Instruction::StartsWith(Arg::decode(input)?, Arg::decode(input)?),
+            40 => Instruction::EndsWith(Arg::decode(input)?, Arg::decode(input)?),
+            tag => {
+                return Err(FormatError::InvalidTag {
+                    kind: "instruction",
+                    tag,
+                });
+            }
+        };
+        Ok(op)
+    }
+}
@@ -1,8 +1,15 @@
-use core::panic;
+use std::{cmp::Ordering, task::Poll};
 
-use vm_lib::{Compilable, Executable, ProcessContext, Stack};
+use vm_lib::{
+    Compilable, CompileError, ConstantPool, ErrorKind, ExecError, ExecResult, Executable, Flow,
+    Handle, Labels, OverflowPolicy, ProcessContext, Stack, StackEffect, Target, Verifiable,
+};
 
-use crate::data_types::{Arg, Data};
+use crate::{
+    collections::CollectionKind,
+    data_types::{Arg, Data},
+    types::DataType,
+};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum BinaryOp {
@@ -10,6 +17,14 @@ pub enum BinaryOp {
     Subtract,
     Multiply,
     Divide,
+    Mod,
+    Pow,
+    // Bitwise for Int/Byte, logical for Bool so conditions combine before a JUMPIF
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
     GT,
     GET,
     LT,
@@ -18,10 +33,41 @@ pub enum BinaryOp {
     NEQ,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    // Bool only
+    Not,
+    BitNot,
+    Abs,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Instruction {
     //Binary operations
     BinaryOp(BinaryOp, Arg, Arg),
+    //Unary operations
+    UnaryOp(UnaryOp, Arg),
+    //Load the chars of a String between two indexes, none leaving that end open
+    Slice(Arg, Arg, Arg),
+    //Load the char index of the first match in a String, none if there is none
+    Find(Arg, Arg),
+    //Replace every match in a String
+    Replace(Arg, Arg, Arg),
+    //Split a String into a List, into chars with an empty separator
+    Split(Arg, Arg),
+    //Join a List of Strings with a separator
+    Join(Arg, Arg),
+    //Convert a String to upper case
+    Upper(Arg),
+    //Convert a String to lower case
+    Lower(Arg),
+    //Strip the whitespace around a String
+    Trim(Arg),
+    //Check whether a String starts with another
+    StartsWith(Arg, Arg),
+    //Check whether a String ends with another
+    EndsWith(Arg, Arg),
     //Store a value in the stack
     Store(Arg),
     //Load a value to the Accumulator
@@ -30,17 +76,60 @@ pub enum Instruction {
     Copy(Arg, Arg),
     //Free a number of values from the
     Free(u8),
+    //Allocate a heap cell holding a value, loading its pointer to the Accumulator
+    Alloc(Arg),
+    //Release the heap cell behind a pointer
+    Dealloc(Arg),
+    //Load the value behind a heap pointer to the Accumulator
+    HeapLoad(Arg),
+    //Overwrite the value behind a heap pointer
+    HeapStore(Arg, Arg),
+    //Build a collection from the last stored values (pairs of them for a Dict)
+    Build(CollectionKind, u8),
+    //Load an item of a collection by index or key
+    Index(Arg, Arg),
+    //Set an item of the collection at a Stack/Accumulator location
+    Set(Arg, Arg, Arg),
+    //Push a value at the end of a List
+    Append(Arg, Arg),
+    //Take the last item of a List into the Accumulator
+    Pop(Arg),
+    //Take an item of a List or an entry of a Dict into the Accumulator
+    Remove(Arg, Arg),
+    //Check whether a collection holds an item (a key for a Dict)
+    Contains(Arg, Arg),
+    //Number of items of a collection or characters of a String
+    Len(Arg),
+    //Store each item of a Tuple/List, which must have exactly that many
+    Unpack(Arg, u8),
+    //Convert a value to another type, loading it to the Accumulator
+    Cast(Arg, DataType),
+    //Load the name of the type of a value to the Accumulator
+    TypeOf(Arg),
+    //Check whether a value has a specific type
+    IsType(Arg, DataType),
     //Jump to a specific instruction
     Jump(Arg),
     //Jump to a specific instruction if the value is not 0
     JumpIf(Arg, Arg),
+    //Call the subroutine at a specific instruction, saving the return address
+    Call(Arg),
+    //Call a host function with the last stored values, loading its result to the Accumulator
+    CallNative(Arg, u8),
+    //Return from a subroutine, keeping the Accumulator as the result
+    Return,
     //Print a value
     Print(Arg),
+    //Load the next line of input to the Accumulator, none at the end of input
+    ReadLine,
+    //Load up to a number of bytes of input to the Accumulator, none at the end of input
+    ReadBytes(Arg),
     //Finish the program
     HALT,
 }
 
 type OpProc = ProcessContext<Data>;
+type OpResult<T = ()> = Result<T, ExecError<Data>>;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum SuperInstruction {
@@ -57,7 +146,7 @@ impl Compilable<Data> for SuperInstruction {
             SuperInstruction::IF(arg, lines) => vec![
                 Self::Simple(Instruction::Jump(arg.clone())),
                 Self::Simple(Instruction::Jump(Arg::Const(Data::Pointer(1)))),
-                Self::Simple(Instruction::Jump(Arg::Const(Data::Pointer(*lines)))),
+                Self::Simple(Instruction::Jump(Arg::Const(Data::Pointer(*lines as u64)))),
             ],
 
             SuperInstruction::Swap(arg1, arg2) => vec![
@@ -82,124 +171,625 @@ impl Compilable<Data> for SuperInstruction {
 }
 
 impl Executable<Data> for Instruction {
-    fn execute(&self, proc: &mut OpProc) -> () {
+    fn execute(&self, proc: &mut OpProc) -> ExecResult<Data> {
         match self {
-            Instruction::BinaryOp(op, a, b) => op.execute(&mut proc.stack, a, b),
+            Instruction::BinaryOp(op, a, b) => op.execute(proc, a, b),
+            Instruction::UnaryOp(op, arg) => op.execute(proc, arg),
+            Instruction::Slice(text, start, end) => Self::slice(proc, text, start, end),
+            Instruction::Find(text, needle) => Self::find(proc, text, needle),
+            Instruction::Replace(text, from, to) => Self::replace(proc, text, from, to),
+            Instruction::Split(text, separator) => Self::split(proc, text, separator),
+            Instruction::Join(list, separator) => Self::join(proc, list, separator),
+            Instruction::Upper(text) => Self::upper(proc, text),
+            Instruction::Lower(text) => Self::lower(proc, text),
+            Instruction::Trim(text) => Self::trim(proc, text),
+            Instruction::StartsWith(text, prefix) => Self::starts_with(proc, text, prefix),
+            Instruction::EndsWith(text, suffix) => Self::ends_with(proc, text, suffix),
             Instruction::Store(arg) => Self::store(proc, arg),
             Instruction::Load(arg) => Self::load(proc, arg),
             Instruction::Copy(src, tgt) => Self::copy(proc, src, tgt),
             Instruction::Free(n) => Self::clean_stack(&mut proc.stack, *n as u64),
+            Instruction::Alloc(arg) => Self::alloc(proc, arg),
+            Instruction::Dealloc(arg) => Self::dealloc(proc, arg),
+            Instruction::HeapLoad(arg) => Self::heap_load(proc, arg),
+            Instruction::HeapStore(ptr, arg) => Self::heap_store(proc, ptr, arg),
+            Instruction::Build(kind, count) => Self::build(proc, *kind, *count),
+            Instruction::Index(collection, key) => Self::index(proc, collection, key),
+            Instruction::Set(target, key, value) => Self::set(proc, target, key, value),
+            Instruction::Append(target, value) => Self::append(proc, target, value),
+            Instruction::Pop(target) => Self::pop(proc, target),
+            Instruction::Remove(target, key) => Self::remove(proc, target, key),
+            Instruction::Contains(collection, value) => Self::contains(proc, collection, value),
+            Instruction::Len(collection) => Self::len(proc, collection),
+            Instruction::Unpack(collection, count) => Self::unpack(proc, collection, *count),
+            Instruction::Cast(arg, target) => Self::cast(proc, arg, *target),
+            Instruction::TypeOf(arg) => Self::type_of(proc, arg),
+            Instruction::IsType(arg, expected) => Self::is_type(proc, arg, *expected),
             Instruction::Jump(arg) => Self::jump(proc, arg),
             Instruction::JumpIf(cond, arg) => Self::jump_if(proc, cond, arg),
+            Instruction::Call(arg) => Self::call(proc, arg),
+            Instruction::CallNative(function, count) => Self::call_native(proc, function, *count),
+            Instruction::Return => Ok(proc.ret()?),
             Instruction::Print(arg) => Self::print(proc, arg),
-            Instruction::HALT => proc.halt(),
-            //_ => unimplemented!(),
+            Instruction::ReadLine => Self::read_line(proc),
+            Instruction::ReadBytes(count) => Self::read_bytes(proc, count),
+            Instruction::HALT => {
+                proc.halt();
+                Ok(())
+            }
+        }
+    }
+
+    fn hoist_constants(&mut self, pool: &mut ConstantPool<Data>) {
+        for arg in self.args_mut() {
+            arg.hoist(pool);
+        }
+    }
+
+    fn resolve_labels(&mut self, ipointer: usize, labels: &Labels) -> Result<(), CompileError> {
+        if let Instruction::Jump(target)
+        | Instruction::JumpIf(_, target)
+        | Instruction::Call(target) = self
+            && let Arg::Label(label) = target
+        {
+            let Some(position) = labels.get(label.as_str()) else {
+                let label = label.to_string();
+                return Err(CompileError::UndefinedLabel { label, ipointer });
+            };
+            let offset = *position as i64 - ipointer as i64 - 1;
+            *target = Arg::Const(Data::Int(offset));
         }
+
+        for arg in self.args_mut() {
+            if let Arg::Label(label) = arg {
+                let label = label.to_string();
+                return Err(CompileError::MisplacedLabel { label, ipointer });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Verifiable<Data> for Instruction {
+    fn flow(&self, ipointer: usize, constants: &[Data]) -> Flow {
+        match self {
+            Instruction::Jump(arg) => Flow::Jump(Self::static_target(ipointer, arg, constants)),
+            Instruction::JumpIf(_, arg) => {
+                Flow::Branch(Self::static_target(ipointer, arg, constants))
+            }
+            Instruction::Call(arg) => Flow::Call(Self::static_target(ipointer, arg, constants)),
+            Instruction::Return => Flow::Return,
+            Instruction::HALT => Flow::Halt,
+            _ => Flow::Next,
+        }
+    }
+
+    fn stack_effect(&self) -> StackEffect {
+        match self {
+            Instruction::Store(arg @ Arg::Ref(_)) => StackEffect {
+                reads: arg.depth(),
+                ..Default::default()
+            },
+            Instruction::Store(_) => StackEffect {
+                pushes: 1,
+                ..Default::default()
+            },
+            Instruction::Free(n) => StackEffect {
+                pops: *n as usize,
+                ..Default::default()
+            },
+            Instruction::Build(kind, count) => StackEffect {
+                pops: kind.values(*count),
+                ..Default::default()
+            },
+            Instruction::Unpack(arg, count) => StackEffect {
+                reads: arg.depth(),
+                pushes: *count as usize,
+                ..Default::default()
+            },
+            Instruction::CallNative(arg, count) => StackEffect {
+                reads: arg.depth(),
+                pops: *count as usize,
+                ..Default::default()
+            },
+            _ => StackEffect {
+                reads: self.args().iter().map(|arg| arg.depth()).max().unwrap_or(0),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn check(&self, constants: &[Data]) -> Vec<String> {
+        let mut problems = vec![];
+        for arg in self.args() {
+            match arg {
+                Arg::ConstIdx(index) if *index >= constants.len() => {
+                    problems.push(format!("constant {} is out of bounds", arg))
+                }
+                Arg::Label(label) => problems.push(format!("unresolved label `{}`", label)),
+                _ => {}
+            }
+        }
+
+        match self {
+            Instruction::Copy(_, tgt @ (Arg::Const(_) | Arg::ConstIdx(_))) => {
+                problems.push(format!("cannot copy into constant {}", tgt))
+            }
+            Instruction::Set(tgt @ (Arg::Const(_) | Arg::ConstIdx(_)), ..)
+            | Instruction::Append(tgt @ (Arg::Const(_) | Arg::ConstIdx(_)), _)
+            | Instruction::Pop(tgt @ (Arg::Const(_) | Arg::ConstIdx(_)))
+            | Instruction::Remove(tgt @ (Arg::Const(_) | Arg::ConstIdx(_)), _) => {
+                problems.push(format!("cannot modify constant {}", tgt))
+            }
+            Instruction::CallNative(arg, _) => {
+                if let Some(value) = arg.constant(constants)
+                    && !matches!(value, Data::Function(_))
+                {
+                    problems.push(format!("{} is not a function", value))
+                }
+            }
+            Instruction::Call(arg) if matches!(arg.constant(constants), Some(Data::None)) => {
+                problems.push("call without a target".to_string())
+            }
+            Instruction::Jump(arg) | Instruction::JumpIf(_, arg) | Instruction::Call(arg) => {
+                if let Some(value) = arg.constant(constants)
+                    && !Self::is_jumpable(value)
+                {
+                    problems.push(format!("{} is not a jump target", value))
+                }
+            }
+            _ => {}
+        }
+        problems
     }
 }
 
 impl Instruction {
-    fn jump(proc: &mut OpProc, arg: &Arg) -> () {
-        let arg = arg.deref(&proc.stack);
+    pub fn args(&self) -> Vec<&Arg> {
+        match self {
+            Instruction::BinaryOp(_, a, b)
+            | Instruction::Copy(a, b)
+            | Instruction::HeapStore(a, b)
+            | Instruction::Index(a, b)
+            | Instruction::Append(a, b)
+            | Instruction::Remove(a, b)
+            | Instruction::Contains(a, b)
+            | Instruction::Find(a, b)
+            | Instruction::Split(a, b)
+            | Instruction::Join(a, b)
+            | Instruction::StartsWith(a, b)
+            | Instruction::EndsWith(a, b)
+            | Instruction::JumpIf(a, b) => vec![a, b],
+            Instruction::Set(a, b, c)
+            | Instruction::Slice(a, b, c)
+            | Instruction::Replace(a, b, c) => vec![a, b, c],
+            Instruction::UnaryOp(_, arg)
+            | Instruction::Store(arg)
+            | Instruction::Load(arg)
+            | Instruction::Alloc(arg)
+            | Instruction::Dealloc(arg)
+            | Instruction::HeapLoad(arg)
+            | Instruction::Pop(arg)
+            | Instruction::Len(arg)
+            | Instruction::Upper(arg)
+            | Instruction::Lower(arg)
+            | Instruction::Trim(arg)
+            | Instruction::Unpack(arg, _)
+            | Instruction::Cast(arg, _)
+            | Instruction::TypeOf(arg)
+            | Instruction::IsType(arg, _)
+            | Instruction::Jump(arg)
+            | Instruction::Call(arg)
+            | Instruction::CallNative(arg, _)
+            | Instruction::Print(arg)
+            | Instruction::ReadBytes(arg) => vec![arg],
+            Instruction::Free(_)
+            | Instruction::Build(..)
+            | Instruction::Return
+            | Instruction::ReadLine
+            | Instruction::HALT => vec![],
+        }
+    }
+
+    pub fn args_mut(&mut self) -> Vec<&mut Arg> {
+        match self {
+            Instruction::BinaryOp(_, a, b)
+            | Instruction::Copy(a, b)
+            | Instruction::HeapStore(a, b)
+            | Instruction::Index(a, b)
+            | Instruction::Append(a, b)
+            | Instruction::Remove(a, b)
+            | Instruction::Contains(a, b)
+            | Instruction::Find(a, b)
+            | Instruction::Split(a, b)
+            | Instruction::Join(a, b)
+            | Instruction::StartsWith(a, b)
+            | Instruction::EndsWith(a, b)
+            | Instruction::JumpIf(a, b) => vec![a, b],
+            Instruction::Set(a, b, c)
+            | Instruction::Slice(a, b, c)
+            | Instruction::Replace(a, b, c) => vec![a, b, c],
+            Instruction::UnaryOp(_, arg)
+            | Instruction::Store(arg)
+            | Instruction::Load(arg)
+            | Instruction::Alloc(arg)
+            | Instruction::Dealloc(arg)
+            | Instruction::HeapLoad(arg)
+            | Instruction::Pop(arg)
+            | Instruction::Len(arg)
+            | Instruction::Upper(arg)
+            | Instruction::Lower(arg)
+            | Instruction::Trim(arg)
+            | Instruction::Unpack(arg, _)
+            | Instruction::Cast(arg, _)
+            | Instruction::TypeOf(arg)
+            | Instruction::IsType(arg, _)
+            | Instruction::Jump(arg)
+            | Instruction::Call(arg)
+            | Instruction::CallNative(arg, _)
+            | Instruction::Print(arg)
+            | Instruction::ReadBytes(arg) => vec![arg],
+            Instruction::Free(_)
+            | Instruction::Build(..)
+            | Instruction::Return
+            | Instruction::ReadLine
+            | Instruction::HALT => vec![],
+        }
+    }
+
+    const fn is_jumpable(value: &Data) -> bool {
+        matches!(
+            value,
+            Data::Byte(_) | Data::Pointer(_) | Data::Int(_) | Data::Bool(_) | Data::None
+        )
+    }
+
+    /// Mirrors `target` for constant operands, pointing past the run loop's increment.
+    fn static_target(ipointer: usize, arg: &Arg, constants: &[Data]) -> Target {
+        let landing = match arg.constant(constants) {
+            Some(Data::None) => ipointer.checked_add(1),
+            Some(value) if Self::is_jumpable(value) => Self::landing(ipointer, value),
+            _ => return Target::Dynamic,
+        };
+        Target::Static(landing.unwrap_or(usize::MAX))
+    }
+
+    /// Instruction pointer to jump to: one before the landing instruction, as the run
+    /// loop increments it afterwards. Landing on instruction 0 wraps it to `usize::MAX`.
+    fn target(proc: &OpProc, arg: &Arg) -> OpResult<Option<usize>> {
+        let arg = arg.deref(proc)?;
+        if matches!(arg, Data::None) {
+            return Ok(None);
+        }
+
+        match Self::landing(proc.get_ipntr(), arg) {
+            Some(landing) => Ok(Some(landing.wrapping_sub(1))),
+            None => Err(ExecError::new(ErrorKind::InvalidJump, vec![arg.clone()])),
+        }
+    }
+
+    /// Instruction executed after jumping from `ipointer`, `None` if it cannot be
+    /// addressed. Bytes and pointers are absolute, the rest relative to the next one.
+    fn landing(ipointer: usize, target: &Data) -> Option<usize> {
+        match target {
+            Data::Byte(ipointer) => Some(*ipointer as usize + 1),
+            Data::Pointer(ipointer) => usize::try_from(*ipointer).ok()?.checked_add(1),
+            Data::Int(offset) => {
+                let offset = isize::try_from(*offset).ok()?;
+                ipointer.checked_add(1)?.checked_add_signed(offset)
+            }
+            Data::Bool(offset) => ipointer.checked_add(1 + *offset as usize),
+            _ => None,
+        }
+    }
 
-        match arg {
-            Data::Byte(ipointer) => proc.goto(*ipointer as usize),
-            Data::Pointer(ipointer) => proc.goto(*ipointer),
+    fn jump(proc: &mut OpProc, arg: &Arg) -> OpResult {
+        if let Some(target) = Self::target(proc, arg)? {
+            proc.goto(target);
+        }
+        Ok(())
+    }
 
-            Data::Int(ipointer) => proc.goto_rel(*ipointer as isize),
-            Data::Bool(ipointer) => proc.goto_rel(*ipointer as isize),
-            Data::None => {}
-            _ => panic!("Jump offset must be an integer"),
+    fn call(proc: &mut OpProc, arg: &Arg) -> OpResult {
+        match Self::target(proc, arg)? {
+            Some(target) => proc.call(target),
+            None => return Err(ExecError::new(ErrorKind::InvalidJump, vec![Data::None])),
         }
+        Ok(())
+    }
+
+    fn call_native(proc: &mut OpProc, function: &Arg, count: u8) -> OpResult {
+        let function = function.deref(proc)?.clone();
+        let Data::Function(name) = &function else {
+            return Err(ExecError::new(ErrorKind::TypeMismatch, vec![function]));
+        };
+
+        proc.call_native(name, count as usize)
+            .map_err(|error| match error.operands.is_empty() {
+                true => ExecError::new(error.kind, vec![function.clone()]),
+                false => error,
+            })
     }
 
-    fn jump_if(proc: &mut OpProc, cond: &Arg, arg: &Arg) -> () {
-        let cond = cond.deref(&proc.stack);
+    fn jump_if(proc: &mut OpProc, cond: &Arg, arg: &Arg) -> OpResult {
+        let cond = cond.deref(proc)?;
 
         match cond {
             Data::Bool(true) | Data::Int(1..) | Data::Float(1.0..) => Self::jump(proc, arg),
-            Data::Bool(false) | Data::Int(0) | Data::Float(0.0) | Data::None => {}
-            _ => panic!("Jump condition must be a boolean"),
+            Data::Bool(false) | Data::Int(0) | Data::Float(0.0) | Data::None => Ok(()),
+            _ => Err(ExecError::new(
+                ErrorKind::InvalidCondition,
+                vec![cond.clone()],
+            )),
         }
     }
 
-    fn print(proc: &OpProc, arg: &Arg) -> () {
-        let value = arg.deref(&proc.stack);
+    fn print(proc: &OpProc, arg: &Arg) -> OpResult {
+        let value = arg.deref(proc)?;
 
         proc.print(value);
+        Ok(())
     }
 
-    fn copy(proc: &mut OpProc, src: &Arg, tgt: &Arg) -> () {
-        let value = src.deref(&proc.stack);
+    fn read_line(proc: &mut OpProc) -> OpResult {
+        let Poll::Ready(line) = proc.read_line()? else {
+            return Ok(());
+        };
+
+        let value = line.map_or(Data::None, |line| Data::String(Box::new(line)));
+        proc.stack.to_register(value);
+        Ok(())
+    }
+
+    fn read_bytes(proc: &mut OpProc, count: &Arg) -> OpResult {
+        let count = match count.deref(proc)? {
+            Data::Int(count) if *count >= 0 => *count as usize,
+            Data::Byte(count) => *count as usize,
+            count => return Err(ExecError::new(ErrorKind::TypeMismatch, vec![count.clone()])),
+        };
+        let Poll::Ready(bytes) = proc.read_bytes(count)? else {
+            return Ok(());
+        };
+
+        let value = bytes.map_or(Data::None, |bytes| {
+            Data::ByteArray(Box::new(bytes.into_boxed_slice()))
+        });
+        proc.stack.to_register(value);
+        Ok(())
+    }
+
+    /// `COPY x, $n` writes the slot `$n` reads, counting down from the last stored value.
+    fn copy(proc: &mut OpProc, src: &Arg, tgt: &Arg) -> OpResult {
+        let value = src.deref(proc)?;
 
         match tgt {
-            Arg::Ref(name) => proc.stack.store_at(*name as usize, value.clone()),
+            Arg::Ref(name) => proc.stack.to_register_at(*name + 1, value.clone())?,
             Arg::Acc => proc.stack.to_register(value.clone()),
-            Arg::Const(_) => panic!("Cannot copy to a constant"),
+            Arg::Const(_) | Arg::ConstIdx(_) | Arg::Label(_) => {
+                let operands = vec![value.clone(), tgt.deref(proc)?.clone()];
+                return Err(ExecError::new(ErrorKind::InvalidTarget, operands));
+            }
         }
+        Ok(())
     }
 
-    fn store(proc: &mut OpProc, arg: &Arg) -> () {
-        let pointer = if let Arg::Ref(rel_pntr) = arg {
-            proc.get_rel_ipntr(-(*rel_pntr as isize))
-        } else {
-            if let Arg::Const(data) = arg {
-                proc.stack.to_register(data.clone());
+    /// Pushes a value and loads a pointer to its slot. `STORE $n` pushes nothing, it only
+    /// loads a pointer to the slot `$n` reads.
+    fn store(proc: &mut OpProc, arg: &Arg) -> OpResult {
+        let pointer = match arg {
+            Arg::Ref(rel_pntr) => proc.stack.below(*rel_pntr + 1)?,
+            Arg::Const(_) | Arg::ConstIdx(_) | Arg::Label(_) => {
+                let value = arg.deref(proc)?.clone();
+                proc.stack.to_register(value);
+                proc.stack.store_register()?
             }
-            proc.stack.store_register()
+            Arg::Acc => proc.stack.store_register()?,
         };
 
-        proc.stack.to_register(Data::Pointer(pointer));
+        proc.stack.to_register(Data::Pointer(pointer as u64));
+        Ok(())
     }
 
-    fn load(proc: &mut OpProc, arg: &Arg) -> () {
+    /// `LOAD $n` reads the slot `$n` names everywhere else, `LOAD acc` follows the
+    /// pointer left in the accumulator by `STORE`.
+    fn load(proc: &mut OpProc, arg: &Arg) -> OpResult {
         let value = match arg {
-            Arg::Const(data) => data,
-            Arg::Ref(name) => proc.stack.peek_register(*name as usize),
-            Arg::Acc => {
-                if let Data::Pointer(pntr) = proc.stack.peek_register(0) {
-                    proc.stack.peek_at(*pntr)
-                } else {
-                    panic!("Cannot \"Load\" from Accumulator")
-                }
-            }
+            Arg::Const(_) | Arg::ConstIdx(_) | Arg::Ref(_) | Arg::Label(_) => arg.deref(proc)?,
+            Arg::Acc => match proc.stack.peek_register(0)? {
+                Data::Pointer(pntr) => proc
+                    .stack
+                    .peek_at(usize::try_from(*pntr).unwrap_or(usize::MAX))?,
+                value => return Err(ExecError::new(ErrorKind::InvalidLoad, vec![value.clone()])),
+            },
         };
         proc.stack.to_register(value.clone());
+        Ok(())
     }
 
-    fn clean_stack(stack: &mut Stack<Data>, n: u64) -> () {
-        for _ in 0..n {
-            stack.pop::<1>();
+    fn handle(proc: &OpProc, arg: &Arg) -> OpResult<Handle> {
+        match arg.deref(proc)? {
+            Data::Pointer(bits) => Ok(Handle::from_bits(*bits)),
+            value => Err(ExecError::new(ErrorKind::InvalidLoad, vec![value.clone()])),
         }
     }
+
+    fn dangling(handle: Handle) -> impl FnOnce(ErrorKind) -> ExecError<Data> {
+        move |kind| ExecError::new(kind, vec![Data::Pointer(handle.to_bits())])
+    }
+
+    fn alloc(proc: &mut OpProc, arg: &Arg) -> OpResult {
+        let value = arg.deref(proc)?.clone();
+        let handle = proc.heap().alloc(value);
+
+        proc.stack.to_register(Data::Pointer(handle.to_bits()));
+        Ok(())
+    }
+
+    fn dealloc(proc: &mut OpProc, arg: &Arg) -> OpResult {
+        let handle = Self::handle(proc, arg)?;
+
+        proc.heap().free(handle).map_err(Self::dangling(handle))?;
+        Ok(())
+    }
+
+    fn heap_load(proc: &mut OpProc, arg: &Arg) -> OpResult {
+        let handle = Self::handle(proc, arg)?;
+        let value = proc
+            .heap()
+            .get(handle)
+            .map_err(Self::dangling(handle))?
+            .clone();
+
+        proc.stack.to_register(value);
+        Ok(())
+    }
+
+    fn heap_store(proc: &mut OpProc, ptr: &Arg, arg: &Arg) -> OpResult {
+        let handle = Self::handle(proc, ptr)?;
+        let value = arg.deref(proc)?.clone();
+
+        proc.heap()
+            .set(handle, value)
+            .map_err(Self::dangling(handle))?;
+        Ok(())
+    }
+
+    fn clean_stack(stack: &mut Stack<Data>, n: u64) -> OpResult {
+        let depth = stack.below(n as usize)?;
+
+        stack.truncate(depth)?;
+        Ok(())
+    }
 }
 
 impl BinaryOp {
-    fn execute(&self, stack: &mut Stack<Data>, a: &Arg, b: &Arg) -> () {
-        let value_a = a.deref(stack);
-        let value_b = b.deref(stack);
+    pub const fn mnemonic(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "ADD",
+            BinaryOp::Subtract => "SUB",
+            BinaryOp::Multiply => "MUL",
+            BinaryOp::Divide => "DIV",
+            BinaryOp::Mod => "MOD",
+            BinaryOp::Pow => "POW",
+            BinaryOp::And => "AND",
+            BinaryOp::Or => "OR",
+            BinaryOp::Xor => "XOR",
+            BinaryOp::Shl => "SHL",
+            BinaryOp::Shr => "SHR",
+            BinaryOp::GT => "GT",
+            BinaryOp::GET => "GET",
+            BinaryOp::LT => "LT",
+            BinaryOp::LET => "LET",
+            BinaryOp::EQ => "EQ",
+            BinaryOp::NEQ => "NEQ",
+        }
+    }
+
+    pub fn from_mnemonic(name: &str) -> Option<Self> {
+        let op = match name {
+            "ADD" => BinaryOp::Add,
+            "SUB" => BinaryOp::Subtract,
+            "MUL" => BinaryOp::Multiply,
+            "DIV" => BinaryOp::Divide,
+            "MOD" => BinaryOp::Mod,
+            "POW" => BinaryOp::Pow,
+            "AND" => BinaryOp::And,
+            "OR" => BinaryOp::Or,
+            "XOR" => BinaryOp::Xor,
+            "SHL" => BinaryOp::Shl,
+            "SHR" => BinaryOp::Shr,
+            "GT" => BinaryOp::GT,
+            "GET" => BinaryOp::GET,
+            "LT" => BinaryOp::LT,
+            "LET" => BinaryOp::LET,
+            "EQ" => BinaryOp::EQ,
+            "NEQ" => BinaryOp::NEQ,
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    fn execute(&self, proc: &mut OpProc, a: &Arg, b: &Arg) -> OpResult {
+        let value_a = a.deref(proc)?;
+        let value_b = b.deref(proc)?;
+        let promoted = Self::promote(value_a, value_b);
+        let (value_a, value_b) = match &promoted {
+            Some((a, b)) => (a, b),
+            None => (value_a, value_b),
+        };
 
         let result = match self {
-            BinaryOp::Add => Self::add(value_a, value_b),
-            BinaryOp::Subtract => Self::substract(value_a, value_b),
-            BinaryOp::Multiply => Self::multiply(value_a, value_b),
-            BinaryOp::Divide => Self::divide(value_a, value_b),
-            BinaryOp::GT => Self::gt(value_a, value_b),
-            BinaryOp::GET => Self::gte(value_a, value_b),
-            BinaryOp::LT => Self::gte(value_b, value_a),
-            BinaryOp::LET => Self::gt(value_b, value_a),
-            BinaryOp::EQ => Self::eq(value_a, value_b),
-            BinaryOp::NEQ => Self::neq(value_a, value_b),
+            BinaryOp::Add => Self::add(value_a, value_b, proc.overflow()),
+            BinaryOp::Subtract => Self::substract(value_a, value_b, proc.overflow()),
+            BinaryOp::Multiply => Self::multiply(value_a, value_b, proc.overflow()),
+            BinaryOp::Divide => Self::divide(value_a, value_b, proc.overflow()),
+            BinaryOp::Mod => Self::modulo(value_a, value_b),
+            BinaryOp::Pow => Self::power(value_a, value_b, proc.overflow()),
+            BinaryOp::And => {
+                Self::bitwise(value_a, value_b, |x, y| x & y, |x, y| x & y, |x, y| x & y)
+            }
+            BinaryOp::Or => {
+                Self::bitwise(value_a, value_b, |x, y| x | y, |x, y| x | y, |x, y| x | y)
+            }
+            BinaryOp::Xor => {
+                Self::bitwise(value_a, value_b, |x, y| x ^ y, |x, y| x ^ y, |x, y| x ^ y)
+            }
+            BinaryOp::Shl => Self::shift(value_a, value_b, proc.overflow(), true),
+            BinaryOp::Shr => Self::shift(value_a, value_b, proc.overflow(), false),
+            BinaryOp::GT => Self::compare(value_a, value_b, Ordering::is_gt),
+            BinaryOp::GET => Self::compare(value_a, value_b, Ordering::is_ge),
+            BinaryOp::LT => Self::compare(value_a, value_b, Ordering::is_lt),
+            BinaryOp::LET => Self::compare(value_a, value_b, Ordering::is_le),
+            // Structural down to nested items. Numbers are promoted first (only at the top
+            // level), any other mix of types is never equal
+            BinaryOp::EQ => Ok(Data::Bool(value_a == value_b)),
+            BinaryOp::NEQ => Ok(Data::Bool(value_a != value_b)),
+        }?;
+
+        proc.stack.to_register(result);
+        Ok(())
+    }
+
+    /// Widens two numbers of different types to the wider one, along `Byte < Int < Float`.
+    /// Returns `None` when they already match or either one is not a number.
+    fn promote(a: &Data, b: &Data) -> Option<(Data, Data)> {
+        let level = |value: &Data| match value {
+            Data::Byte(_) => Some(0),
+            Data::Int(_) => Some(1),
+            Data::Float(_) => Some(2),
+            _ => None,
         };
+        let (level_a, level_b) = (level(a)?, level(b)?);
+        if level_a == level_b {
+            return None;
+        }
 
-        stack.to_register(result);
+        let widen = |value: &Data| match (value, level_a.max(level_b)) {
+            (Data::Byte(value), 1) => Data::Int(*value as i64),
+            (Data::Byte(value), 2) => Data::Float(*value as f64),
+            (Data::Int(value), 2) => Data::Float(*value as f64),
+            (value, _) => value.clone(),
+        };
+        Some((widen(a), widen(b)))
     }
 
-    fn add(a: &Data, b: &Data) -> Data {
+    fn add(a: &Data, b: &Data, policy: OverflowPolicy) -> OpResult<Data> {
         let result = match (a, b) {
-            (Data::Int(a), Data::Int(b)) => Data::Int(a + b),
+            (Data::Int(x), Data::Int(y)) => Data::Int(Self::integer(
+                policy,
+                x.checked_add(*y),
+                x.wrapping_add(*y),
+                x.saturating_add(*y),
+                &[a, b],
+            )?),
             (Data::Float(a), Data::Float(b)) => Data::Float(a + b),
-            (Data::Byte(a), Data::Byte(b)) => Data::Byte(a + b),
+            (Data::Byte(x), Data::Byte(y)) => Data::Byte(Self::integer(
+                policy,
+                x.checked_add(*y),
+                x.wrapping_add(*y),
+                x.saturating_add(*y),
+                &[a, b],
+            )?),
             (Data::ByteArray(a), Data::ByteArray(b)) => {
                 let value = Box::new([*a.clone(), *b.clone()].concat().into_boxed_slice());
                 Data::ByteArray(value)
@@ -209,86 +799,299 @@ impl BinaryOp {
                 value.push_str(b);
                 Data::String(Box::new(value))
             }
-            _ => panic!("Type mismatch"),
+            _ => return Err(Self::mismatch(a, b)),
         };
 
-        result
+        Ok(result)
     }
 
-    fn substract(a: &Data, b: &Data) -> Data {
+    fn substract(a: &Data, b: &Data, policy: OverflowPolicy) -> OpResult<Data> {
         let result = match (a, b) {
-            (Data::Int(a), Data::Int(b)) => Data::Int(a - b),
+            (Data::Int(x), Data::Int(y)) => Data::Int(Self::integer(
+                policy,
+                x.checked_sub(*y),
+                x.wrapping_sub(*y),
+                x.saturating_sub(*y),
+                &[a, b],
+            )?),
             (Data::Float(a), Data::Float(b)) => Data::Float(a - b),
-            (Data::Byte(a), Data::Byte(b)) => Data::Byte(a - b),
-            _ => panic!("Type mismatch"),
+            (Data::Byte(x), Data::Byte(y)) => Data::Byte(Self::integer(
+                policy,
+                x.checked_sub(*y),
+                x.wrapping_sub(*y),
+                x.saturating_sub(*y),
+                &[a, b],
+            )?),
+            _ => return Err(Self::mismatch(a, b)),
         };
 
-        result
+        Ok(result)
     }
 
-    fn multiply(a: &Data, b: &Data) -> Data {
+    fn multiply(a: &Data, b: &Data, policy: OverflowPolicy) -> OpResult<Data> {
         let result = match (a, b) {
-            (Data::Int(a), Data::Int(b)) => Data::Int(a * b),
+            (Data::Int(x), Data::Int(y)) => Data::Int(Self::integer(
+                policy,
+                x.checked_mul(*y),
+                x.wrapping_mul(*y),
+                x.saturating_mul(*y),
+                &[a, b],
+            )?),
             (Data::Float(a), Data::Float(b)) => Data::Float(a * b),
-            (Data::Byte(a), Data::Byte(b)) => Data::Byte(a * b),
-            _ => panic!("Type mismatch"),
+            (Data::Byte(x), Data::Byte(y)) => Data::Byte(Self::integer(
+                policy,
+                x.checked_mul(*y),
+                x.wrapping_mul(*y),
+                x.saturating_mul(*y),
+                &[a, b],
+            )?),
+            _ => return Err(Self::mismatch(a, b)),
         };
 
-        result
+        Ok(result)
     }
 
-    fn divide(a: &Data, b: &Data) -> Data {
+    /// Division by zero is an error under every policy; only `Int::MIN / -1` overflows.
+    fn divide(a: &Data, b: &Data, policy: OverflowPolicy) -> OpResult<Data> {
         let result = match (a, b) {
-            (Data::Int(a), Data::Int(b)) => Data::Int(a / b),
+            (Data::Int(_), Data::Int(0)) | (Data::Byte(_), Data::Byte(0)) => {
+                return Err(Self::division_by_zero(a, b));
+            }
+            (Data::Int(x), Data::Int(y)) => Data::Int(Self::integer(
+                policy,
+                x.checked_div(*y),
+                x.wrapping_div(*y),
+                x.saturating_div(*y),
+                &[a, b],
+            )?),
             (Data::Float(a), Data::Float(b)) => Data::Float(a / b),
             (Data::Byte(a), Data::Byte(b)) => Data::Byte(a / b),
-            _ => panic!("Type mismatch"),
+            _ => return Err(Self::mismatch(a, b)),
         };
 
-        result
+        Ok(result)
     }
 
-    fn gt(a: &Data, b: &Data) -> Data {
+    /// Remainder with the sign of the dividend. `Int::MIN % -1` is 0, so it never overflows.
+    fn modulo(a: &Data, b: &Data) -> OpResult<Data> {
         let result = match (a, b) {
-            (Data::Int(a), Data::Int(b)) => Data::Bool(a > b),
-            (Data::Float(a), Data::Float(b)) => Data::Bool(a > b),
-            (Data::Byte(a), Data::Byte(b)) => Data::Bool(a > b),
-            _ => panic!("Type mismatch"),
+            (Data::Int(_), Data::Int(0)) | (Data::Byte(_), Data::Byte(0)) => {
+                return Err(Self::division_by_zero(a, b));
+            }
+            (Data::Int(x), Data::Int(y)) => Data::Int(x.wrapping_rem(*y)),
+            (Data::Float(x), Data::Float(y)) => Data::Float(x % y),
+            (Data::Byte(x), Data::Byte(y)) => Data::Byte(x % y),
+            _ => return Err(Self::mismatch(a, b)),
         };
 
-        result
+        Ok(result)
     }
 
-    fn gte(a: &Data, b: &Data) -> Data {
+    /// A negative `Int` exponent gives a `Float`, as the result is rarely whole.
+    fn power(a: &Data, b: &Data, policy: OverflowPolicy) -> OpResult<Data> {
         let result = match (a, b) {
-            (Data::Int(a), Data::Int(b)) => Data::Bool(a >= b),
-            (Data::Float(a), Data::Float(b)) => Data::Bool(a >= b),
-            (Data::Byte(a), Data::Byte(b)) => Data::Bool(a >= b),
-            _ => panic!("Type mismatch"),
+            (Data::Int(x), Data::Int(y)) if *y < 0 => Data::Float((*x as f64).powf(*y as f64)),
+            (Data::Int(x), Data::Int(y)) => {
+                let checked = Self::checked_power(*x, *y as u64);
+                let saturated = match *x < 0 && *y % 2 == 1 {
+                    true => i64::MIN,
+                    false => i64::MAX,
+                };
+                Data::Int(Self::integer(
+                    policy,
+                    checked,
+                    Self::wrapping_power(*x, *y as u64),
+                    checked.unwrap_or(saturated),
+                    &[a, b],
+                )?)
+            }
+            (Data::Float(x), Data::Float(y)) => Data::Float(x.powf(*y)),
+            (Data::Byte(x), Data::Byte(y)) => Data::Byte(Self::integer(
+                policy,
+                x.checked_pow(u32::from(*y)),
+                x.wrapping_pow(u32::from(*y)),
+                x.saturating_pow(u32::from(*y)),
+                &[a, b],
+            )?),
+            _ => return Err(Self::mismatch(a, b)),
         };
 
+        Ok(result)
+    }
+
+    /// Repeated squaring over the whole exponent, as `i64::checked_pow` takes a `u32`.
+    fn checked_power(mut base: i64, mut exponent: u64) -> Option<i64> {
+        let mut result: i64 = 1;
+        loop {
+            if exponent & 1 == 1 {
+                result = result.checked_mul(base)?;
+            }
+            exponent >>= 1;
+            if exponent == 0 {
+                return Some(result);
+            }
+            base = base.checked_mul(base)?;
+        }
+    }
+
+    fn wrapping_power(mut base: i64, mut exponent: u64) -> i64 {
+        let mut result: i64 = 1;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.wrapping_mul(base);
+            }
+            exponent >>= 1;
+            base = base.wrapping_mul(base);
+        }
         result
     }
 
-    fn eq(a: &Data, b: &Data) -> Data {
+    fn bitwise(
+        a: &Data,
+        b: &Data,
+        int: fn(i64, i64) -> i64,
+        byte: fn(u8, u8) -> u8,
+        logical: fn(bool, bool) -> bool,
+    ) -> OpResult<Data> {
         let result = match (a, b) {
-            (Data::Int(a), Data::Int(b)) => Data::Bool(a == b),
-            (Data::Float(a), Data::Float(b)) => Data::Bool(a == b),
-            (Data::Byte(a), Data::Byte(b)) => Data::Bool(a == b),
-            _ => panic!("Type mismatch"),
+            (Data::Int(x), Data::Int(y)) => Data::Int(int(*x, *y)),
+            (Data::Byte(x), Data::Byte(y)) => Data::Byte(byte(*x, *y)),
+            (Data::Bool(x), Data::Bool(y)) => Data::Bool(logical(*x, *y)),
+            _ => return Err(Self::mismatch(a, b)),
         };
 
-        result
+        Ok(result)
     }
 
-    fn neq(a: &Data, b: &Data) -> Data {
+    /// Shifting by the width of the type or more overflows: wrapping masks the amount and
+    /// saturating shifts every bit out. `Int` shifts right keep the sign.
+    fn shift(a: &Data, b: &Data, policy: OverflowPolicy, left: bool) -> OpResult<Data> {
         let result = match (a, b) {
-            (Data::Int(a), Data::Int(b)) => Data::Bool(a != b),
-            (Data::Float(a), Data::Float(b)) => Data::Bool(a != b),
-            (Data::Byte(a), Data::Byte(b)) => Data::Bool(a != b),
-            _ => panic!("Type mismatch"),
+            (Data::Int(x), Data::Int(y)) => {
+                let amount = u32::try_from(*y).ok();
+                let (shifted, wrapped) = match left {
+                    true => (
+                        amount.and_then(|n| x.checked_shl(n)),
+                        x.wrapping_shl(*y as u32),
+                    ),
+                    false => (
+                        amount.and_then(|n| x.checked_shr(n)),
+                        x.wrapping_shr(*y as u32),
+                    ),
+                };
+                let emptied = if left || *x >= 0 { 0 } else { -1 };
+                Data::Int(Self::integer(
+                    policy,
+                    shifted,
+                    wrapped,
+                    shifted.unwrap_or(emptied),
+                    &[a, b],
+                )?)
+            }
+            (Data::Byte(x), Data::Byte(y)) => {
+                let (shifted, wrapped) = match left {
+                    true => (x.checked_shl(u32::from(*y)), x.wrapping_shl(u32::from(*y))),
+                    false => (x.checked_shr(u32::from(*y)), x.wrapping_shr(u32::from(*y))),
+                };
+                Data::Byte(Self::integer(
+                    policy,
+                    shifted,
+                    wrapped,
+                    shifted.unwrap_or(0),
+                    &[a, b],
+                )?)
+            }
+            _ => return Err(Self::mismatch(a, b)),
         };
 
-        result
+        Ok(result)
+    }
+
+    /// Picks the result allowed by `policy`, given every way of computing it.
+    pub(crate) fn integer<T>(
+        policy: OverflowPolicy,
+        checked: Option<T>,
+        wrapping: T,
+        saturating: T,
+        operands: &[&Data],
+    ) -> OpResult<T> {
+        match policy {
+            OverflowPolicy::Wrapping => Ok(wrapping),
+            OverflowPolicy::Saturating => Ok(saturating),
+            OverflowPolicy::Checked => checked.ok_or_else(|| {
+                let operands = operands.iter().map(|value| (*value).clone()).collect();
+                ExecError::new(ErrorKind::IntegerOverflow, operands)
+            }),
+        }
+    }
+
+    /// Orders values of the same type by `Data::cmp`; other types cannot be compared.
+    fn compare(a: &Data, b: &Data, accept: fn(Ordering) -> bool) -> OpResult<Data> {
+        match a.rank() == b.rank() {
+            true => Ok(Data::Bool(accept(a.cmp(b)))),
+            false => Err(Self::mismatch(a, b)),
+        }
+    }
+
+    fn division_by_zero(a: &Data, b: &Data) -> ExecError<Data> {
+        ExecError::new(ErrorKind::DivisionByZero, vec![a.clone(), b.clone()])
+    }
+
+    fn mismatch(a: &Data, b: &Data) -> ExecError<Data> {
+        ExecError::new(ErrorKind::TypeMismatch, vec![a.clone(), b.clone()])
+    }
+}
+
+impl UnaryOp {
+    pub const fn mnemonic(&self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "NEG",
+            UnaryOp::Not => "NOT",
+            UnaryOp::BitNot => "BITNOT",
+            UnaryOp::Abs => "ABS",
+        }
+    }
+
+    pub fn from_mnemonic(name: &str) -> Option<Self> {
+        let op = match name {
+            "NEG" => UnaryOp::Neg,
+            "NOT" => UnaryOp::Not,
+            "BITNOT" => UnaryOp::BitNot,
+            "ABS" => UnaryOp::Abs,
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    fn execute(&self, proc: &mut OpProc, arg: &Arg) -> OpResult {
+        let policy = proc.overflow();
+        let value = arg.deref(proc)?;
+
+        let result = match (self, value) {
+            (UnaryOp::Neg, Data::Int(x)) => Data::Int(BinaryOp::integer(
+                policy,
+                x.checked_neg(),
+                x.wrapping_neg(),
+                x.saturating_neg(),
+                &[value],
+            )?),
+            (UnaryOp::Neg, Data::Float(x)) => Data::Float(-x),
+            (UnaryOp::Not, Data::Bool(x)) => Data::Bool(!x),
+            (UnaryOp::BitNot, Data::Int(x)) => Data::Int(!x),
+            (UnaryOp::BitNot, Data::Byte(x)) => Data::Byte(!x),
+            (UnaryOp::Abs, Data::Int(x)) => Data::Int(BinaryOp::integer(
+                policy,
+                x.checked_abs(),
+                x.wrapping_abs(),
+                x.saturating_abs(),
+                &[value],
+            )?),
+            (UnaryOp::Abs, Data::Float(x)) => Data::Float(x.abs()),
+            (UnaryOp::Abs, Data::Byte(x)) => Data::Byte(*x),
+            _ => return Err(ExecError::new(ErrorKind::TypeMismatch, vec![value.clone()])),
+        };
+
+        proc.stack.to_register(result);
+        Ok(())
     }
 }
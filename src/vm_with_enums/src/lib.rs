@@ -1,7 +1,11 @@
-#![feature(thin_box)]
-
+pub mod assembler;
+pub mod collections;
 pub mod data_types;
+pub mod disassembler;
+mod encoding;
 pub mod instructions;
+mod strings;
+pub mod types;
 
 #[cfg(test)]
 mod test;
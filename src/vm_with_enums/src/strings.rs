@@ -0,0 +1,167 @@
+use vm_lib::{ErrorKind, ExecError, ProcessContext};
+
+use crate::{
+    data_types::{Arg, Data},
+    instructions::Instruction,
+};
+
+type OpProc = ProcessContext<Data>;
+type OpResult<T = ()> = Result<T, ExecError<Data>>;
+
+// ------------------------
+// MARK: IMPLEMENTS
+//------------------------
+
+/// Operations on `Data::String`. Indexes and lengths count chars, not bytes.
+impl Instruction {
+    /// Chars from `start` up to `end`, with `none` as an open end.
+    pub(crate) fn slice(proc: &mut OpProc, text: &Arg, start: &Arg, end: &Arg) -> OpResult {
+        let values = [text.deref(proc)?, start.deref(proc)?, end.deref(proc)?];
+        let Data::String(text) = values[0] else {
+            return Err(Self::not_a_string(&values));
+        };
+
+        let len = text.chars().count();
+        let start = Self::char_index(values[1], 0, &values)?;
+        let end = Self::char_index(values[2], len, &values)?;
+        if start > end || end > len {
+            let operands = values.iter().map(|value| (*value).clone()).collect();
+            return Err(ExecError::new(ErrorKind::IndexOutOfBounds, operands));
+        }
+        let slice = text.chars().skip(start).take(end - start).collect();
+        proc.stack.to_register(Self::string(slice));
+        Ok(())
+    }
+
+    /// Index of the first match or `none`.
+    pub(crate) fn find(proc: &mut OpProc, text: &Arg, needle: &Arg) -> OpResult {
+        let [text, needle] = Self::strings(proc, [text, needle])?;
+
+        let found = match text.find(needle) {
+            Some(byte) => Data::Int(text[..byte].chars().count() as i64),
+            None => Data::None,
+        };
+        proc.stack.to_register(found);
+        Ok(())
+    }
+
+    /// Replaces every match.
+    pub(crate) fn replace(proc: &mut OpProc, text: &Arg, from: &Arg, to: &Arg) -> OpResult {
+        let [text, from, to] = Self::strings(proc, [text, from, to])?;
+
+        let replaced = Self::string(text.replace(from, to));
+        proc.stack.to_register(replaced);
+        Ok(())
+    }
+
+    /// Splits into a List, an empty separator splits into chars.
+    pub(crate) fn split(proc: &mut OpProc, text: &Arg, separator: &Arg) -> OpResult {
+        let [text, separator] = Self::strings(proc, [text, separator])?;
+
+        let parts: Vec<_> = match separator.is_empty() {
+            true => text.chars().map(|c| Self::string(c.to_string())).collect(),
+            false => text
+                .split(separator)
+                .map(|part| Self::string(part.to_string()))
+                .collect(),
+        };
+        proc.stack.to_register(Data::List(Box::new(parts)));
+        Ok(())
+    }
+
+    /// Joins a List of strings.
+    pub(crate) fn join(proc: &mut OpProc, list: &Arg, separator: &Arg) -> OpResult {
+        let values = [list.deref(proc)?, separator.deref(proc)?];
+        let [Data::List(items), Data::String(separator)] = values else {
+            return Err(Self::not_a_string(&values));
+        };
+
+        let parts = items
+            .iter()
+            .map(|item| match item {
+                Data::String(part) => Ok(part.as_str()),
+                _ => Err(Self::not_a_string(&values)),
+            })
+            .collect::<OpResult<Vec<_>>>()?;
+        let joined = Self::string(parts.join(separator));
+        proc.stack.to_register(joined);
+        Ok(())
+    }
+
+    pub(crate) fn upper(proc: &mut OpProc, text: &Arg) -> OpResult {
+        let [text] = Self::strings(proc, [text])?;
+
+        let upper = Self::string(text.to_uppercase());
+        proc.stack.to_register(upper);
+        Ok(())
+    }
+
+    pub(crate) fn lower(proc: &mut OpProc, text: &Arg) -> OpResult {
+        let [text] = Self::strings(proc, [text])?;
+
+        let lower = Self::string(text.to_lowercase());
+        proc.stack.to_register(lower);
+        Ok(())
+    }
+
+    pub(crate) fn trim(proc: &mut OpProc, text: &Arg) -> OpResult {
+        let [text] = Self::strings(proc, [text])?;
+
+        let trimmed = Self::string(text.trim().to_string());
+        proc.stack.to_register(trimmed);
+        Ok(())
+    }
+
+    pub(crate) fn starts_with(proc: &mut OpProc, text: &Arg, prefix: &Arg) -> OpResult {
+        let [text, prefix] = Self::strings(proc, [text, prefix])?;
+
+        let found = Data::Bool(text.starts_with(prefix));
+        proc.stack.to_register(found);
+        Ok(())
+    }
+
+    pub(crate) fn ends_with(proc: &mut OpProc, text: &Arg, suffix: &Arg) -> OpResult {
+        let [text, suffix] = Self::strings(proc, [text, suffix])?;
+
+        let found = Data::Bool(text.ends_with(suffix));
+        proc.stack.to_register(found);
+        Ok(())
+    }
+
+    /// Dereferences operands that must all be strings.
+    fn strings<'a, const N: usize>(proc: &'a OpProc, args: [&'a Arg; N]) -> OpResult<[&'a str; N]> {
+        let mut values = [&Data::None; N];
+        for (value, arg) in values.iter_mut().zip(args) {
+            *value = arg.deref(proc)?;
+        }
+
+        let mut texts = [""; N];
+        for (text, value) in texts.iter_mut().zip(values) {
+            let Data::String(value) = value else {
+                return Err(Self::not_a_string(&values));
+            };
+            *text = value.as_str();
+        }
+        Ok(texts)
+    }
+
+    fn char_index(value: &Data, open: usize, values: &[&Data]) -> OpResult<usize> {
+        match value {
+            Data::None => Ok(open),
+            Data::Int(index) => usize::try_from(*index).map_err(|_| {
+                let operands = values.iter().map(|value| (*value).clone()).collect();
+                ExecError::new(ErrorKind::IndexOutOfBounds, operands)
+            }),
+            _ => Err(Self::not_a_string(values)),
+        }
+    }
+
+    fn string(value: String) -> Data {
+        Data::String(Box::new(value))
+    }
+
+    fn not_a_string(values: &[&Data]) -> ExecError<Data> {
+        let operands = values.iter().map(|value| (*value).clone()).collect();
+        ExecError::new(ErrorKind::TypeMismatch, operands)
+    }
+}
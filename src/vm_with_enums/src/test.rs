@@ -1,11 +1,24 @@
-use std::time::Instant;
+use std::{
+    cell::RefCell,
+    collections::{HashSet, VecDeque},
+    io,
+    rc::Rc,
+    time::Instant,
+};
 
 use log::info;
 
-use vm_lib::{ProgramCode, StackMachine};
+use vm_lib::{
+    ByteCode, CompileError, ConstantPool, DebugInfo, Diagnostic, DiagnosticKind, ErrorKind,
+    ExecError, FORMAT_VERSION, FormatError, GcConfig, Handle, Heap, Input, InputFile, InputScript,
+    MAX_DEPTH, NativeType, Natives, OutputBuffer, OverflowPolicy, Process, ProcessStatus,
+    ProgramCode, Quantum, Runnable, SharedInput, Stack, StackMachine, StackSize, Stop, VmError,
+};
 
 use crate::{
+    assembler::assemble,
     data_types::{Arg, Data},
+    disassembler::disassemble,
     instructions::{BinaryOp, Instruction},
 };
 
@@ -21,11 +34,11 @@ fn test() {
         let elapsed = timer.elapsed();
         println!("Execution time: {:?}", elapsed);
     }
-    println!("");
-    println!("");
+    println!();
+    println!();
     println!("-------------------------------------------");
-    println!("");
-    println!("");
+    println!();
+    println!();
     {
         let code = vec![
             // result = 4 + 24
@@ -46,11 +59,11 @@ fn test() {
 
         let program = ProgramCode::new(code.clone(), vec![]);
 
-        info!("CODE: {:?}", &code);
+        info!("CODE: {:?}", code);
         let mut vm = StackMachine::new();
 
-        vm.add_process(program);
-        vm.run();
+        vm.add_process(program).unwrap();
+        assert!(vm.run().is_empty());
     }
 }
 
@@ -72,11 +85,11 @@ fn test2() {
         let elapsed = timer.elapsed();
         println!("Execution time: {:?}", elapsed);
     }
-    println!("");
-    println!("");
+    println!();
+    println!();
     println!("-------------------------------------------");
-    println!("");
-    println!("");
+    println!();
+    println!();
     {
         let code = vec![
             // i = 1.0
@@ -104,9 +117,1489 @@ fn test2() {
         ];
 
         let program = ProgramCode::new(code.clone(), vec![]);
-        info!("CODE: {:?}", &code);
+        info!("CODE: {:?}", code);
         let mut vm = StackMachine::new();
-        vm.add_process(program);
-        vm.run();
+        vm.add_process(program).unwrap();
+        assert!(vm.run().is_empty());
+    }
+}
+
+#[test_log::test]
+fn test_crashed_process() {
+    let faulty = vec![
+        Instruction::Store(Arg::Const(Data::Int(0))),
+        Instruction::BinaryOp(BinaryOp::Divide, Arg::Const(Data::Int(7)), Arg::Ref(0)),
+        Instruction::HALT,
+    ];
+    let healthy = vec![
+        Instruction::BinaryOp(
+            BinaryOp::Add,
+            Arg::Const(Data::Int(1)),
+            Arg::Const(Data::Int(2)),
+        ),
+        Instruction::Print(Arg::Acc),
+        Instruction::HALT,
+    ];
+
+    let mut vm = StackMachine::new();
+    vm.add_process(ProgramCode::new(faulty, vec![])).unwrap();
+    vm.add_process(ProgramCode::new(healthy, vec![])).unwrap();
+
+    let errors = vm.run();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].kind, ErrorKind::DivisionByZero);
+    assert_eq!(errors[0].ipointer, 1);
+    assert_eq!(errors[0].operands, vec![Data::Int(7), Data::Int(0)]);
+
+    // Jumps that cannot be addressed crash instead of wrapping the instruction pointer
+    let error = run("STORE -3\nJUMPIF true, $0").unwrap_err();
+    assert_eq!(error.kind, ErrorKind::InvalidJump);
+    assert_eq!(error.operands, vec![Data::Int(-3)]);
+    let far = assemble("JUMPIF true, @18446744073709551615\nHALT")
+        .unwrap()
+        .compile()
+        .unwrap();
+    assert_eq!(
+        far.verify(),
+        Err(vec![Diagnostic {
+            ipointer: 0,
+            kind: DiagnosticKind::JumpOutOfBounds { target: usize::MAX }
+        }])
+    );
+    let heap = Rc::new(RefCell::new(Heap::new()));
+    let Stop::Crashed(error) = Process::new(StackSize::default(), heap, far).resume() else {
+        panic!("an unaddressable jump must crash");
+    };
+    assert_eq!(error.kind, ErrorKind::InvalidJump);
+
+    // Landing on the first instruction is still fine
+    let source = "
+        top:    JUMPIF acc, end
+                LOAD true
+                JUMP top
+        end:    HALT
+    ";
+    assert_eq!(run(source), Ok(Data::Bool(true)));
+}
+
+#[test_log::test]
+fn test_time_slices() {
+    let endless = vec![
+        Instruction::Load(Arg::Const(Data::Int(0))),
+        Instruction::JumpIf(Arg::Const(Data::Bool(true)), Arg::Const(Data::Int(-2))),
+        Instruction::HALT,
+    ];
+    let short = vec![
+        Instruction::Print(Arg::Const(Data::Int(42))),
+        Instruction::HALT,
+    ];
+
+    let mut vm = StackMachine::with_quantum(Quantum::Instructions(100));
+    vm.add_process(ProgramCode::new(endless, vec![])).unwrap();
+    vm.add_process(ProgramCode::new(short, vec![])).unwrap();
+
+    // The endless process must yield so the short one gets to halt.
+    assert!(vm.step().is_empty());
+    assert_eq!(vm.proceses.len(), 1);
+    assert!(!vm.proceses[0].is_finished());
+}
+
+#[test_log::test]
+fn test_blocked_input() {
+    // Lines arrive only when pushed, reads before that would block.
+    #[derive(Default)]
+    struct Pipe {
+        lines: VecDeque<String>,
     }
+
+    impl Input for Pipe {
+        fn read_line(&mut self) -> io::Result<Option<String>> {
+            match self.lines.pop_front() {
+                Some(line) => Ok(Some(line)),
+                None => Err(io::ErrorKind::WouldBlock.into()),
+            }
+        }
+
+        fn read_bytes(&mut self, _count: usize) -> io::Result<Option<Vec<u8>>> {
+            Err(io::ErrorKind::WouldBlock.into())
+        }
+    }
+
+    let pipe = Rc::new(RefCell::new(Pipe::default()));
+    let output = Rc::new(RefCell::new(OutputBuffer::default()));
+    let mut vm = StackMachine::with_quantum(Quantum::Instructions(100));
+    vm.input = pipe.clone();
+    vm.output = output.clone();
+    vm.add_process(assemble("READLINE\nPRINT acc\nHALT").unwrap())
+        .unwrap();
+    vm.add_process(assemble("PRINT 1\nHALT").unwrap()).unwrap();
+
+    // The waiting reader must not hold up the other process.
+    assert!(vm.step().is_empty());
+    assert_eq!(vm.proceses.len(), 1);
+    assert!(vm.step().is_empty());
+    assert_eq!(vm.proceses.len(), 1);
+
+    // The read is retried once a line is ready.
+    pipe.borrow_mut().lines.push_back("late".to_string());
+    assert!(vm.step().is_empty());
+    assert!(vm.proceses.is_empty());
+    let output = output.borrow();
+    let printed: Vec<_> = output
+        .lines
+        .iter()
+        .filter(|line| !line.starts_with("Execution time: "))
+        .collect();
+    assert_eq!(printed, ["Int(1)", "String(\"late\")"]);
+
+    let pipe = Rc::new(RefCell::new(Pipe::default()));
+    let bytecode = assemble("READLINE\nHALT").unwrap().compile().unwrap();
+    let heap = Rc::new(RefCell::new(Heap::new()));
+    let mut process = Process::new(StackSize::default(), heap, bytecode).with_input(pipe.clone());
+    assert_eq!(process.run(&Quantum::default()), ProcessStatus::Blocked);
+    assert_eq!(process.context().get_ipntr(), 0);
+    pipe.borrow_mut().lines.push_back("late".to_string());
+    assert_eq!(process.run(&Quantum::default()), ProcessStatus::Finished);
+    assert_eq!(*process.context().stack.accumulator(), value("\"late\""));
+}
+
+#[test_log::test]
+fn test_call_return() {
+    // Bail out to a crashing load if the accumulator isn't `expected`.
+    const FAIL: usize = 15;
+    let check = |expected: i64| {
+        [
+            Instruction::BinaryOp(BinaryOp::NEQ, Arg::Acc, Arg::Const(Data::Int(expected))),
+            Instruction::JumpIf(Arg::Acc, Arg::Const(Data::Pointer(FAIL as u64 - 1))),
+        ]
+    };
+
+    let mut code = vec![
+        // square(3)
+        Instruction::Store(Arg::Const(Data::Int(3))),
+        Instruction::Call(Arg::Const(Data::Int(9))),
+        Instruction::Free(1),
+    ];
+    code.extend(check(9));
+    code.extend([
+        // square(5)
+        Instruction::Store(Arg::Const(Data::Int(5))),
+        Instruction::Call(Arg::Const(Data::Int(4))),
+        Instruction::Free(1),
+    ]);
+    code.extend(check(25));
+    code.extend([
+        Instruction::HALT,
+        // square(x): a local copy is dropped on return
+        Instruction::Load(Arg::Ref(0)),
+        Instruction::Store(Arg::Acc),
+        Instruction::BinaryOp(BinaryOp::Multiply, Arg::Ref(0), Arg::Ref(1)),
+        Instruction::Return,
+        Instruction::Load(Arg::Acc),
+        Instruction::HALT,
+    ]);
+    assert_eq!(code[FAIL], Instruction::Load(Arg::Acc));
+
+    let mut vm = StackMachine::new();
+    vm.add_process(ProgramCode::new(code, vec![])).unwrap();
+    assert!(vm.run().is_empty());
+
+    // `$n` names the same slot whatever the instruction
+    let source = "
+                STORE 1
+                STORE 2
+                COPY 20, $0         ; overwrites the 2
+                LOAD $1
+                STORE $0            ; pointer to the 20, nothing pushed
+                LOAD acc
+                ADD acc, $1
+    ";
+    assert_eq!(run(source), Ok(Data::Int(21)));
+}
+
+#[test_log::test]
+fn test_assembler() {
+    let source = r#"
+        ; i = 1.0
+                STORE 1.0
+        loop:   LT 1e12, $0         ; while i < MAX
+                JUMPIF acc, end
+                MUL $0, 1.000001
+                COPY acc, $0
+                JUMP loop
+        end:    PRINT $0
+                PRINT "TERMINADO \"JEJEJE\""
+                HALT
+        .const [1, -2.5, 7u8, (true, none), x"00ff", fn"main", @3, {}]
+    "#;
+
+    let program = assemble(source).unwrap();
+    let bytecode = program.compile().unwrap();
+    assert_eq!(
+        bytecode.get()[0],
+        Instruction::Store(Arg::Const(Data::Float(1.0)))
+    );
+    assert_eq!(
+        bytecode.get()[2],
+        Instruction::JumpIf(Arg::Acc, Arg::Const(Data::Int(3)))
+    );
+    assert_eq!(
+        bytecode.get()[5],
+        Instruction::Jump(Arg::Const(Data::Int(-5)))
+    );
+    assert_eq!(bytecode.get()[7], Instruction::Print(Arg::ConstIdx(1)));
+    assert_eq!(
+        bytecode.get_constants(),
+        [
+            Data::List(Box::new(vec![
+                Data::Int(1),
+                Data::Float(-2.5),
+                Data::Byte(7),
+                Data::Tuple(Box::new(Box::new([Data::Bool(true), Data::None]))),
+                Data::ByteArray(Box::new(Box::new([0x00, 0xff]))),
+                Data::Function(Box::new("main".to_string())),
+                Data::Pointer(3),
+                Data::Dict(Default::default()),
+            ])),
+            Data::String(Box::new("TERMINADO \"JEJEJE\"".to_string())),
+        ]
+    );
+
+    let mut vm = StackMachine::new();
+    vm.add_process(program).unwrap();
+    assert!(vm.run().is_empty());
+
+    let error = assemble("  JUMP nowhere").unwrap_err();
+    assert_eq!((error.line, error.column), (1, 8));
+    let error = assemble("HALT\n  ADD 1, \"open").unwrap_err();
+    assert_eq!((error.line, error.column), (2, 10));
+    let error = assemble("a: HALT\na: HALT").unwrap_err();
+    assert_eq!((error.line, error.column), (2, 1));
+
+    // Integers too large for an Int are rejected rather than read as a Float
+    let error = assemble("HALT\n  LOAD 99999999999999999999").unwrap_err();
+    assert_eq!((error.line, error.column), (2, 8));
+    assert_eq!(
+        error.message,
+        "integer literal `99999999999999999999` out of range"
+    );
+    let error = assemble("STORE -9_223_372_036_854_775_809").unwrap_err();
+    assert!(error.message.contains("out of range"));
+    assert_eq!(value("-9_223_372_036_854_775_808"), Data::Int(i64::MIN));
+    assert_eq!(value("1e20"), Data::Float(1e20));
+    assert_eq!(value("99999999999999999999.0"), Data::Float(1e20));
+}
+
+#[test_log::test]
+fn test_disassembler() {
+    let source = r#"
+                STORE 1.0
+        loop:   LT 1e12, $0
+                JUMPIF acc, end
+                MUL $0, 1.000001
+                COPY acc, $0
+                CALL sub
+                JUMP loop
+        end:    PRINT "tab\t\"quoted\""
+                JUMP @0
+                HALT
+        sub:    RETURN
+        .const [-0.0, nan, 255u8, x"beef", (fn"f", @1), none]
+    "#;
+    let bytecode = assemble(source).unwrap().compile().unwrap();
+
+    let text = disassemble(&bytecode);
+    info!("DISASSEMBLY:\n{}", text);
+    assert!(text.contains("JUMPIF acc, end") && text.contains("; 0002 -> 0007"));
+    assert!(text.contains("JUMP @0") && text.contains("; 0008 -> 0001"));
+
+    let roundtrip = assemble(&text).unwrap().compile().unwrap();
+    assert_eq!(roundtrip.get(), bytecode.get());
+    assert_eq!(disassemble(&roundtrip), text);
+
+    // Without debug symbols, targets get generated labels.
+    let stripped = ByteCode::new(bytecode.get().into(), bytecode.get_constants().into());
+    let text = disassemble(&stripped);
+    assert!(text.contains("JUMPIF acc, L0007") && text.contains("L0007:  PRINT"));
+    let roundtrip = assemble(&text).unwrap().compile().unwrap();
+    assert_eq!(roundtrip.get(), bytecode.get());
+
+    // Labels as wide as the column or wider stay apart from the mnemonic
+    let bytecode = assemble("longname: JUMP seven\nseven:  HALT")
+        .unwrap()
+        .compile()
+        .unwrap();
+    let text = disassemble(&bytecode);
+    assert!(text.contains("longname: JUMP seven") && text.contains("seven:  HALT"));
+    let roundtrip = assemble(&text).unwrap().compile().unwrap();
+    assert_eq!(roundtrip.get(), bytecode.get());
+}
+
+#[test_log::test]
+fn test_labels() {
+    let mut program = ProgramCode::new(vec![], vec![]);
+    program
+        .push(Instruction::Store(Arg::Const(Data::Int(3))))
+        .label("loop")
+        .push(Instruction::BinaryOp(
+            BinaryOp::Subtract,
+            Arg::Ref(0),
+            Arg::Const(Data::Int(1)),
+        ))
+        .push(Instruction::Copy(Arg::Acc, Arg::Ref(0)))
+        .push(Instruction::JumpIf(
+            Arg::Acc,
+            Arg::Label(Box::new("loop".into())),
+        ))
+        .push(Instruction::Jump(Arg::Label(Box::new("end".into()))))
+        .push(Instruction::Print(Arg::Const(Data::Int(-1))))
+        .label("end")
+        .push(Instruction::HALT);
+
+    let bytecode = program.compile().unwrap();
+    assert_eq!(
+        bytecode.get()[3],
+        Instruction::JumpIf(Arg::Acc, Arg::Const(Data::Int(-3)))
+    );
+    assert_eq!(
+        bytecode.get()[4],
+        Instruction::Jump(Arg::Const(Data::Int(1)))
+    );
+
+    let mut vm = StackMachine::new();
+    vm.add_process(program).unwrap();
+    assert!(vm.run().is_empty());
+
+    let mut program = ProgramCode::new(
+        vec![Instruction::Jump(Arg::Label(Box::new("x".into())))],
+        vec![],
+    );
+    program.label("y");
+    assert!(matches!(
+        program.compile(),
+        Err(CompileError::UndefinedLabel { ipointer: 0, .. })
+    ));
+    program.label("y");
+    assert!(matches!(
+        program.compile(),
+        Err(CompileError::DuplicateLabel { .. })
+    ));
+
+    let program = ProgramCode::new(
+        vec![Instruction::Print(Arg::Label(Box::new("x".into())))],
+        vec![],
+    );
+    assert!(matches!(
+        program.compile(),
+        Err(CompileError::MisplacedLabel { .. })
+    ));
+}
+
+#[test_log::test]
+fn test_bytecode_file() {
+    let source = r#"
+                STORE 1.5
+        loop:   SUB $0, 1
+                CALL done
+                JUMPIF acc, loop
+                PRINT [7u8, x"0102", (fn"f", @9, none), {}, "üñí"]
+        done:   RETURN
+        .const -42
+        .const nan
+    "#;
+    let debug = DebugInfo {
+        symbols: vec![(1, "loop".to_string()), (5, "done".to_string())],
+    };
+    let bytecode = assemble(source).unwrap().compile().unwrap();
+    assert_eq!(bytecode.get_debug(), Some(&debug));
+
+    let mut file = vec![];
+    bytecode.write_to(&mut file).unwrap();
+    let loaded = ByteCode::<Instruction, Data>::read_from(&mut file.as_slice()).unwrap();
+    assert_eq!(loaded.get(), bytecode.get());
+    assert_eq!(loaded.get_constants()[0], Data::Int(-42));
+    assert!(matches!(loaded.get_constants()[1], Data::Float(value) if value.is_nan()));
+    assert_eq!(loaded.get_debug(), Some(&debug));
+
+    let truncated = &file[..file.len() - 3];
+    let error = ByteCode::<Instruction, Data>::read_from(&mut &truncated[..]).unwrap_err();
+    assert!(matches!(error, FormatError::Truncated));
+
+    let mut newer = file.clone();
+    newer[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+    let error = ByteCode::<Instruction, Data>::read_from(&mut newer.as_slice()).unwrap_err();
+    assert!(matches!(error, FormatError::UnsupportedVersion(_)));
+
+    let error = ByteCode::<Instruction, Data>::read_from(&mut &b"#!/bin/sh"[..]).unwrap_err();
+    assert!(matches!(error, FormatError::BadMagic));
+
+    // Every instruction survives both the text and the binary round trip
+    let source = r#"
+                ALLOC 1
+                STORE acc
+                HSTORE $0, 2
+                HLOAD $0
+                DEALLOC $0
+                FREE 1
+                STORE [1, 2]
+                APPEND $0, 3
+                SET $0, 0, 10
+                POP $0
+                INDEX $0, 0
+                REMOVE {"a": 1}, "a"
+                CONTAINS $0, 2
+                LEN $0
+                LIST 1
+                UNPACK acc, 1
+                TUPLE 1
+                DICT 0
+                SLICE "abc", 0, none
+                FIND "abc", "b"
+                REPLACE "abc", "b", "x"
+                SPLIT "a,b", ","
+                JOIN acc, "-"
+                UPPER acc
+                LOWER acc
+                TRIM acc
+                STARTSWITH acc, "a"
+                ENDSWITH "abc", "c"
+                MOD 7, 3
+                POW 2, 3
+                AND 1, 1
+                OR 1, 2
+                XOR 1, 3
+                SHL 1, 2
+                SHR 8, 1
+                NEG 1
+                NOT true
+                BITNOT 0u8
+                ABS -1
+                CAST 1, Float
+                TYPEOF acc
+                ISTYPE acc, String
+                CALLNATIVE fn"f", 0
+                READLINE
+                READBYTES 2
+                HALT
+    "#;
+    let bytecode = assemble(source).unwrap().compile().unwrap();
+    let text = disassemble(&bytecode);
+    assert_eq!(
+        assemble(&text).unwrap().compile().unwrap().get(),
+        bytecode.get()
+    );
+    let mut file = vec![];
+    bytecode.write_to(&mut file).unwrap();
+    let loaded = ByteCode::<Instruction, Data>::read_from(&mut file.as_slice()).unwrap();
+    assert_eq!(loaded.get(), bytecode.get());
+
+    let unresolved = ByteCode::<Instruction, Data>::new(
+        [Instruction::Jump(Arg::Label(Box::new("x".into())))].into(),
+        [].into(),
+    );
+    let error = unresolved.write_to(&mut vec![]).unwrap_err();
+    assert!(matches!(error, FormatError::UnresolvedLabel(label) if label == "x"));
+
+    let nested = |depth: usize| {
+        let mut value = Data::None;
+        for _ in 0..depth {
+            value = Data::List(Box::new(vec![value]));
+        }
+        let bytecode = ByteCode::<Instruction, Data>::new([].into(), [value].into());
+        let mut file = vec![];
+        bytecode.write_to(&mut file).unwrap();
+        ByteCode::<Instruction, Data>::read_from(&mut file.as_slice())
+    };
+    assert!(nested(MAX_DEPTH).is_ok());
+    assert!(matches!(nested(MAX_DEPTH + 1), Err(FormatError::TooDeep)));
+
+    // Read code is verified like compiled code before it is scheduled
+    let read = |source: &str| {
+        let mut file = vec![];
+        let bytecode = assemble(source).unwrap().compile().unwrap();
+        bytecode.write_to(&mut file).unwrap();
+        ByteCode::<Instruction, Data>::read_from(&mut file.as_slice()).unwrap()
+    };
+    let mut vm = StackMachine::new();
+    let error = vm.add_bytecode(read("PRINT 1")).unwrap_err();
+    assert!(matches!(error, CompileError::Verification(_)));
+    vm.add_bytecode(read("PRINT 1\nHALT")).unwrap();
+    assert!(vm.run().is_empty());
+}
+
+#[test_log::test]
+fn test_constant_pool() {
+    let greeting = || Arg::Const(Data::String(Box::new("hello".to_string())));
+    let code = vec![
+        Instruction::Print(greeting()),
+        Instruction::Store(greeting()),
+        Instruction::BinaryOp(BinaryOp::Add, Arg::Ref(0), greeting()),
+        Instruction::BinaryOp(
+            BinaryOp::EQ,
+            Arg::Const(Data::Int(1)),
+            Arg::Const(Data::Int(1)),
+        ),
+        Instruction::Print(Arg::ConstIdx(0)),
+        Instruction::HALT,
+    ];
+    let constants = vec![Data::String(Box::new("pooled".to_string()))];
+    let program = ProgramCode::new(code, constants);
+
+    let bytecode = program.compile().unwrap();
+    assert_eq!(bytecode.get_constants().len(), 2);
+    assert_eq!(bytecode.get()[0], Instruction::Print(Arg::ConstIdx(1)));
+    assert_eq!(bytecode.get()[1], Instruction::Store(Arg::ConstIdx(1)));
+    assert_eq!(
+        bytecode.get()[2],
+        Instruction::BinaryOp(BinaryOp::Add, Arg::Ref(0), Arg::ConstIdx(1))
+    );
+    assert_eq!(
+        bytecode.get()[3],
+        Instruction::BinaryOp(
+            BinaryOp::EQ,
+            Arg::Const(Data::Int(1)),
+            Arg::Const(Data::Int(1))
+        )
+    );
+
+    let mut vm = StackMachine::new();
+    vm.add_process(program).unwrap();
+    assert!(vm.run().is_empty());
+
+    // Values that only compare equal keep their own slots
+    let source = "
+        LOAD [-0.0]
+        LOAD [0.0]
+        LOAD [0.0]
+        LOAD (-0.0, 1)
+        LOAD (0.0, 1)
+        LOAD {1: -0.0}
+        LOAD {1: 0.0}
+        HALT
+    ";
+    let bytecode = assemble(source).unwrap().compile().unwrap();
+    let slots: Vec<_> = bytecode.get()[..7]
+        .iter()
+        .map(|op| match op {
+            Instruction::Load(Arg::ConstIdx(index)) => *index,
+            op => panic!("unexpected {op:?}"),
+        })
+        .collect();
+    assert_eq!(slots, [0, 1, 1, 2, 3, 4, 5]);
+    assert_eq!(bytecode.get_constants()[0].to_string(), "[-0.0]");
+    assert_eq!(bytecode.get_constants()[1].to_string(), "[0.0]");
+
+    // Repeated pool entries keep resolving to their first slot
+    let mut pool = ConstantPool::new(vec![Data::Int(1), Data::Int(2), Data::Int(1)]);
+    assert_eq!(pool.intern(Data::Int(1)), 0);
+    assert_eq!(pool.intern(Data::Int(2)), 1);
+    assert_eq!(pool.intern(Data::Int(3)), 3);
+    assert_eq!(pool.intern(Data::Int(3)), 3);
+    for value in 0..1000 {
+        pool.intern(Data::Int(value));
+    }
+    assert_eq!(pool.get().len(), 1001);
+    assert_eq!(pool.intern(Data::Int(999)), 1000);
+
+    let mut vm = StackMachine::new();
+    let error = vm
+        .add_process(ProgramCode::new(
+            vec![Instruction::Print(Arg::ConstIdx(3)), Instruction::HALT],
+            vec![],
+        ))
+        .unwrap_err();
+    let CompileError::Verification(diagnostics) = error else {
+        panic!("unexpected error {:?}", error);
+    };
+    assert_eq!(
+        diagnostics[0].kind,
+        DiagnosticKind::InvalidOperand("constant #3 is out of bounds".to_string())
+    );
+}
+
+#[test_log::test]
+fn test_verifier() {
+    let verify = |source: &str| assemble(source).unwrap().compile().unwrap().verify();
+    let at = |ipointer: usize, kind: DiagnosticKind| Diagnostic { ipointer, kind };
+
+    let program = "
+                STORE 3
+                CALL square
+                FREE 1
+                HALT
+        square: MUL $0, $0
+                RETURN
+    ";
+    assert_eq!(verify(program), Ok(()));
+
+    assert_eq!(
+        verify("PRINT 1"),
+        Err(vec![
+            at(0, DiagnosticKind::FallsOffEnd),
+            at(0, DiagnosticKind::NoTermination),
+        ])
+    );
+    assert_eq!(
+        verify("JUMPIF acc, @7\nHALT"),
+        Err(vec![at(0, DiagnosticKind::JumpOutOfBounds { target: 8 })])
+    );
+    assert_eq!(
+        verify("STORE 1\nFREE 2\nHALT"),
+        Err(vec![at(
+            1,
+            DiagnosticKind::StackUnderflow {
+                depth: 1,
+                required: 2
+            }
+        )])
+    );
+    assert_eq!(
+        verify("COPY acc, #0\nHALT\n.const 1"),
+        Err(vec![at(
+            0,
+            DiagnosticKind::InvalidOperand("cannot copy into constant #0".to_string())
+        )])
+    );
+    assert_eq!(
+        verify("JUMPIF acc, end\nSTORE 1\nend: HALT"),
+        Err(vec![at(
+            2,
+            DiagnosticKind::UnbalancedStack {
+                expected: 0,
+                found: 1
+            }
+        )])
+    );
+    assert_eq!(
+        verify("RETURN"),
+        Err(vec![
+            at(0, DiagnosticKind::ReturnOutsideCall),
+            at(0, DiagnosticKind::NoTermination),
+        ])
+    );
+
+    let mut vm = StackMachine::new();
+    let error = vm.add_process(assemble("PRINT 1").unwrap()).unwrap_err();
+    assert!(matches!(error, CompileError::Verification(_)));
+    assert!(vm.proceses.is_empty());
+}
+
+#[test_log::test]
+fn test_stack_bounds() {
+    let mut vm = StackMachine::new();
+    vm.stack_size.limit = 64;
+    let recursion = "
+                CALL f
+                HALT
+        f:      STORE acc
+                CALL f
+                RETURN
+    ";
+    vm.add_process(assemble(recursion).unwrap()).unwrap();
+    let errors = vm.run();
+    assert_eq!(
+        errors[0].kind,
+        ErrorKind::StackOverflow {
+            depth: 63,
+            capacity: 64
+        }
+    );
+    assert_eq!(errors[0].ipointer, 2);
+
+    let mut vm = StackMachine::new();
+    vm.stack_size.limit = 64;
+    let reads_below = "
+                CALL f
+                HALT
+        f:      PRINT $3
+                RETURN
+    ";
+    vm.add_process(assemble(reads_below).unwrap()).unwrap();
+    let errors = vm.run();
+    assert_eq!(
+        errors[0].kind,
+        ErrorKind::StackUnderflow {
+            depth: 0,
+            capacity: 64
+        }
+    );
+
+    // Slots above the top hold no value either, whatever the capacity
+    let error = run("STORE 1\nLOAD @5\nLOAD acc").unwrap_err();
+    assert!(matches!(
+        error.kind,
+        ErrorKind::StackUnderflow { depth: 1, .. }
+    ));
+    let mut stack = Stack::new(8);
+    let underflow = ErrorKind::StackUnderflow {
+        depth: 0,
+        capacity: 8,
+    };
+    assert_eq!(stack.peek_at(1), Err(underflow.clone()));
+    assert_eq!(stack.store_at(1, Data::Int(1)), Err(underflow));
+}
+
+#[test_log::test]
+fn test_growable_stack() {
+    let mut stack = Stack::<Data>::growable(2, 8);
+    assert_eq!(stack.allocated(), 2);
+    for _ in 0..7 {
+        stack.store_register().unwrap();
+    }
+    assert_eq!(stack.allocated(), 8);
+    assert_eq!(
+        stack.store_register(),
+        Err(ErrorKind::StackOverflow {
+            depth: 7,
+            capacity: 8
+        })
+    );
+
+    // Each level keeps its argument and the one passed down on the stack.
+    let countdown = "
+                STORE 5000
+                CALL down
+                FREE 1
+                HALT
+        down:   EQ $0, 0
+                JUMPIF acc, done
+                SUB $0, 1
+                STORE acc
+                CALL down
+                FREE 1
+        done:   RETURN
+    ";
+    let mut vm = StackMachine::new();
+    vm.add_process(assemble(countdown).unwrap()).unwrap();
+    assert!(vm.run().is_empty());
+
+    let mut vm = StackMachine::new();
+    vm.stack_size = StackSize {
+        initial: 4,
+        limit: 1024,
+    };
+    vm.add_process(assemble(countdown).unwrap()).unwrap();
+    let errors = vm.run();
+    assert!(matches!(
+        errors[0].kind,
+        ErrorKind::StackOverflow { capacity: 1024, .. }
+    ));
+}
+
+#[test_log::test]
+fn test_heap() {
+    let source = "
+                ALLOC 1
+                STORE acc
+                HSTORE $0, 7
+                HLOAD $0
+                EQ acc, 7
+                JUMPIF acc, ok
+                HALT
+        ok:     DEALLOC $0
+                ALLOC 2             ; reuses the freed cell
+                HLOAD $0            ; but the old pointer is stale
+                HALT
+    ";
+    let mut vm = StackMachine::new();
+    vm.add_process(assemble(source).unwrap()).unwrap();
+    let errors = vm.run();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].kind, ErrorKind::DanglingPointer);
+    assert_eq!(errors[0].ipointer, 9);
+
+    let heap = vm.heap.borrow();
+    assert_eq!(heap.len(), 1);
+    let Data::Pointer(bits) = errors[0].operands[0] else {
+        panic!("unexpected operands {:?}", errors[0].operands);
+    };
+    let stale = Handle::from_bits(bits);
+    assert_eq!(heap.get(stale), Err(ErrorKind::DanglingPointer));
+    let fresh = Handle {
+        generation: stale.generation + 1,
+        ..stale
+    };
+    assert_eq!(heap.get(fresh), Ok(&Data::Int(2)));
+
+    // Packed into 64 bits whatever the pointer width
+    let last = Handle {
+        index: u32::MAX,
+        generation: u32::MAX,
+    };
+    assert_eq!(last.to_bits(), u64::MAX);
+    assert_eq!(Handle::from_bits(last.to_bits()), last);
+}
+
+#[test_log::test]
+fn test_garbage_collector() {
+    let source = "
+                ALLOC 42
+                STORE acc           ; $1 stays reachable
+                STORE 0             ; $0 counts the garbage cells
+        loop:   ALLOC 7
+                ADD $0, 1
+                COPY acc, $0
+                NEQ $0, 1000
+                JUMPIF acc, loop
+                HLOAD $1
+                EQ acc, 42
+                JUMPIF acc, ok
+                HLOAD 0
+        ok:     HALT
+    ";
+    let mut vm = StackMachine::with_quantum(Quantum::Instructions(50));
+    vm.gc = GcConfig {
+        threshold: 16,
+        growth: 2,
+    };
+    vm.add_process(assemble(source).unwrap()).unwrap();
+    assert!(vm.run().is_empty());
+
+    let heap = vm.heap.borrow();
+    let stats = heap.stats();
+    assert!(stats.collections > 0);
+    assert!(heap.len() <= 32);
+    assert_eq!(stats.cells_reclaimed + heap.len(), 1001);
+    assert!(stats.total_pause >= stats.last_pause);
+    drop(heap);
+
+    // Cells reachable only through other cells survive.
+    let mut heap = vm.heap.borrow_mut();
+    let inner = heap.alloc(Data::Int(1));
+    let outer = heap.alloc(Data::List(Box::new(vec![Data::Pointer(inner.to_bits())])));
+    let garbage = heap.alloc(Data::Int(2));
+    heap.collect([&Data::Tuple(Box::new(Box::new([Data::Pointer(
+        outer.to_bits(),
+    )])))]);
+    assert_eq!(heap.get(inner), Ok(&Data::Int(1)));
+    assert_eq!(heap.get(garbage), Err(ErrorKind::DanglingPointer));
+
+    // Reclaimed bytes include what the collected values point to
+    let before = heap.stats().bytes_reclaimed;
+    let text = Data::String(Box::new("x".repeat(1000)));
+    let size = text.size();
+    heap.alloc(text);
+    heap.collect([&Data::Pointer(outer.to_bits())]);
+    assert!(size > 1000);
+    assert_eq!(heap.stats().bytes_reclaimed - before, size);
+}
+
+#[test_log::test]
+fn test_data_ordering() {
+    let mut values = [
+        Data::None,
+        Data::String(Box::new("b".to_string())),
+        Data::Float(f64::NAN),
+        Data::Int(2),
+        Data::Float(-0.0),
+        Data::String(Box::new("a".to_string())),
+        Data::Float(0.0),
+        Data::Int(-1),
+    ];
+    values.sort();
+    assert_eq!(
+        values.iter().map(Data::to_string).collect::<Vec<_>>(),
+        ["-1", "2", "-0.0", "0.0", "NaN", "\"a\"", "\"b\"", "none"]
+    );
+    assert_eq!(Data::Float(f64::NAN), Data::Float(f64::NAN));
+    let unique: HashSet<_> = [Data::Float(f64::NAN), Data::Float(f64::NAN), Data::Int(1)].into();
+    assert_eq!(unique.len(), 2);
+
+    // Both zeros are the same value, as keys too
+    assert_eq!(run("EQ 0.0, -0.0"), Ok(Data::Bool(true)));
+    assert_eq!(run("LT -0.0, 0.0"), Ok(Data::Bool(false)));
+    let unique: HashSet<_> = [Data::Float(0.0), Data::Float(-0.0)].into();
+    assert_eq!(unique.len(), 1);
+
+    // So are every NaN, including the negative one `0.0 / 0.0` gives on x86
+    let computed = run("DIV 0.0, 0.0").unwrap();
+    let odd = Data::Float(f64::from_bits(f64::NAN.to_bits() | 1));
+    for nan in [Data::Float(-f64::NAN), computed, odd] {
+        assert_eq!(nan, Data::Float(f64::NAN));
+        assert!(nan > Data::Float(f64::INFINITY));
+        let unique: HashSet<_> = [nan, Data::Float(f64::NAN)].into();
+        assert_eq!(unique.len(), 1);
+    }
+    let mut floats = [Data::Float(f64::NEG_INFINITY), Data::Float(-f64::NAN)];
+    floats.sort();
+    assert_eq!(floats[0], Data::Float(f64::NEG_INFINITY));
+
+    let source = r#"
+                .const {"b": [1, 2], "a": {none: nan}}
+                EQ #0, {"a": {none: nan}, "b": [1, 2]}
+                JUMPIF acc, ok
+                HALT
+        ok:     LT "abc", "abd"
+                JUMPIF acc, ok2
+                HALT
+        ok2:    EQ 1, "1"
+                JUMPIF acc, end
+                LT 1, "1"
+        end:    HALT
+    "#;
+    let program = assemble(source).unwrap();
+    let bytecode = program.compile().unwrap();
+    let mut bytes = vec![];
+    bytecode.write_to(&mut bytes).unwrap();
+    let decoded = ByteCode::<Instruction, Data>::read_from(&mut bytes.as_slice()).unwrap();
+    assert_eq!(decoded.get_constants(), bytecode.get_constants());
+    let text = disassemble(&bytecode);
+    assert!(text.contains(r#".const {"a": {none: NaN}, "b": [1, 2]}"#));
+    assert_eq!(
+        assemble(&text).unwrap().compile().unwrap().get(),
+        bytecode.get()
+    );
+
+    let mut vm = StackMachine::new();
+    vm.add_process(assemble(source).unwrap()).unwrap();
+    let errors = vm.run();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].kind, ErrorKind::TypeMismatch);
+    assert_eq!(errors[0].ipointer, 8);
+}
+
+#[test_log::test]
+fn test_collections() {
+    let list = "
+                STORE 1
+                STORE 2
+                STORE 3
+                LIST 3
+                STORE acc
+                APPEND $0, 4
+                SET $0, 0, 10
+                LOAD $0
+    ";
+    assert_eq!(run(list), Ok(value("[10, 2, 3, 4]")));
+    assert_eq!(run("LOAD [1, 2]\nPOP acc"), Ok(Data::Int(2)));
+    assert_eq!(run("INDEX [10, 2], 0"), Ok(Data::Int(10)));
+
+    let dict = r#"
+                STORE "a"
+                STORE 1
+                STORE "b"
+                STORE 2
+                DICT 2
+    "#;
+    assert_eq!(run(dict), Ok(value(r#"{"a": 1, "b": 2}"#)));
+    assert_eq!(
+        run("STORE {\"a\": 1, \"b\": 2}\nREMOVE $0, \"a\""),
+        Ok(Data::Int(1))
+    );
+    assert_eq!(
+        run("STORE {\"a\": 1, \"b\": 2}\nREMOVE $0, \"a\"\nLOAD $0"),
+        Ok(value(r#"{"b": 2}"#))
+    );
+    assert_eq!(run("CONTAINS {\"b\": 2}, \"a\""), Ok(Data::Bool(false)));
+    assert_eq!(
+        run("STORE {}\nSET $0, \"c\", [1]\nINDEX $0, \"c\""),
+        Ok(value("[1]"))
+    );
+    assert_eq!(run("LEN {\"a\": 1, \"b\": 2}"), Ok(Data::Int(2)));
+    assert_eq!(
+        run("UNPACK (\"x\", 7), 2\nTUPLE 2"),
+        Ok(value("(\"x\", 7)"))
+    );
+
+    let error = |source: &str| run(source).unwrap_err().kind;
+    assert_eq!(error("INDEX [1], 1"), ErrorKind::IndexOutOfBounds);
+    assert_eq!(error("INDEX {1: 2}, 2"), ErrorKind::KeyNotFound);
+    assert_eq!(error("LOAD []\nPOP acc"), ErrorKind::IndexOutOfBounds);
+    assert_eq!(error("UNPACK (1, 2), 3"), ErrorKind::LengthMismatch);
+    assert_eq!(error("LEN 5"), ErrorKind::TypeMismatch);
+}
+
+#[test_log::test]
+fn test_strings() {
+    let text = "\"¡hölà wörld!\"";
+    assert_eq!(run(&format!("SLICE {text}, 1, 5")), Ok(value("\"hölà\"")));
+    assert_eq!(
+        run(&format!("SLICE {text}, 6, none")),
+        Ok(value("\"wörld!\""))
+    );
+    assert_eq!(run(&format!("FIND {text}, \"wö\"")), Ok(Data::Int(6)));
+    assert_eq!(run("FIND \"abc\", \"z\""), Ok(Data::None));
+    assert_eq!(
+        run("REPLACE \"a-b-c\", \"-\", \"→\""),
+        Ok(value("\"a→b→c\""))
+    );
+    assert_eq!(
+        run("SPLIT \"a,b,,c\", \",\""),
+        Ok(value(r#"["a", "b", "", "c"]"#))
+    );
+    assert_eq!(
+        run("SPLIT \"añb\", \"\"\nJOIN acc, \"/\""),
+        Ok(value("\"a/ñ/b\""))
+    );
+    assert_eq!(run("UPPER \"straße\""), Ok(value("\"STRASSE\"")));
+    assert_eq!(run("LOWER \"ÀÉ\""), Ok(value("\"àé\"")));
+    assert_eq!(run("TRIM \"  x \t\""), Ok(value("\"x\"")));
+    assert_eq!(run("STARTSWITH \"ñandú\", \"ña\""), Ok(Data::Bool(true)));
+    assert_eq!(run("ENDSWITH \"ñandú\", \"dú\""), Ok(Data::Bool(true)));
+    assert_eq!(run("LEN \"ñandú\""), Ok(Data::Int(5)));
+    assert_eq!(run("LT \"abc\", \"abd\""), Ok(Data::Bool(true)));
+
+    let error = |source: &str| run(source).unwrap_err().kind;
+    assert_eq!(error("SLICE \"ab\", 1, 3"), ErrorKind::IndexOutOfBounds);
+    assert_eq!(error("JOIN [\"a\", 1], \"\""), ErrorKind::TypeMismatch);
+    assert_eq!(error("UPPER 1"), ErrorKind::TypeMismatch);
+    assert_eq!(
+        assemble("TRIM \"a\", \"b\"").unwrap_err().message,
+        "`TRIM` expects 1 operand(s), found 2"
+    );
+}
+
+#[test_log::test]
+fn test_numeric_promotion() {
+    assert_eq!(run("ADD 1, 2.5"), Ok(Data::Float(3.5)));
+    assert_eq!(run("ADD 2u8, 3"), Ok(Data::Int(5)));
+    assert_eq!(run("MUL 2u8, 1.5"), Ok(Data::Float(3.0)));
+    assert_eq!(run("DIV 7, 2.0"), Ok(Data::Float(3.5)));
+    assert_eq!(run("EQ 1, 1.0"), Ok(Data::Bool(true)));
+    assert_eq!(run("NEQ 3u8, 3"), Ok(Data::Bool(false)));
+    assert_eq!(run("LT 1u8, 2"), Ok(Data::Bool(true)));
+    assert_eq!(run("GT 2.5, 2"), Ok(Data::Bool(true)));
+
+    let error = run("DIV 1, 0u8").unwrap_err();
+    assert_eq!(error.kind, ErrorKind::DivisionByZero);
+    assert_eq!(error.operands, vec![Data::Int(1), Data::Int(0)]);
+    let error = run("ADD 1, \"a\"").unwrap_err();
+    assert_eq!(error.kind, ErrorKind::TypeMismatch);
+    assert_eq!(
+        error.operands,
+        vec![Data::Int(1), Data::String(Box::new("a".into()))]
+    );
+}
+
+#[test_log::test]
+fn test_overflow_policy() {
+    let run = |source: &str, overflow: OverflowPolicy| {
+        run_with(source, |process| process.with_overflow(overflow))
+    };
+
+    let wrapping = OverflowPolicy::Wrapping;
+    assert_eq!(
+        run("ADD 9223372036854775807, 1", wrapping),
+        Ok(Data::Int(i64::MIN))
+    );
+    assert_eq!(
+        run("DIV -9223372036854775808, -1", wrapping),
+        Ok(Data::Int(i64::MIN))
+    );
+    assert_eq!(run("MUL 16u8, 17u8", wrapping), Ok(Data::Byte(16)));
+
+    let saturating = OverflowPolicy::Saturating;
+    assert_eq!(
+        run("ADD 9223372036854775807, 1", saturating),
+        Ok(Data::Int(i64::MAX))
+    );
+    assert_eq!(
+        run("DIV -9223372036854775808, -1", saturating),
+        Ok(Data::Int(i64::MAX))
+    );
+    assert_eq!(run("SUB 1u8, 2u8", saturating), Ok(Data::Byte(0)));
+
+    let checked = OverflowPolicy::Checked;
+    let error = run("ADD 9223372036854775807, 1", checked).unwrap_err();
+    assert_eq!(error.kind, ErrorKind::IntegerOverflow);
+    assert_eq!(error.operands, vec![Data::Int(i64::MAX), Data::Int(1)]);
+    let error = run("DIV -9223372036854775808, -1", checked).unwrap_err();
+    assert_eq!(error.kind, ErrorKind::IntegerOverflow);
+    let error = run("MUL 16u8, 16u8", checked).unwrap_err();
+    assert_eq!(error.kind, ErrorKind::IntegerOverflow);
+    assert_eq!(run("SUB 200u8, 100u8", checked), Ok(Data::Byte(100)));
+    assert_eq!(run("ADD 2.5, 1e308", checked), Ok(Data::Float(1e308)));
+
+    // Dividing by zero has no wrapped or saturated result under any policy
+    for overflow in [wrapping, saturating, checked] {
+        for source in ["DIV 1, 0", "MOD 1, 0", "DIV 1u8, 0u8", "MOD 1u8, 0u8"] {
+            let error = run(source, overflow).unwrap_err();
+            assert_eq!(error.kind, ErrorKind::DivisionByZero);
+        }
+    }
+
+    // The policy is taken when a process is added, so both run side by side
+    let mut vm = StackMachine::new();
+    vm.add_process(assemble("ADD 9223372036854775807, 1\nHALT").unwrap())
+        .unwrap();
+    vm.overflow = OverflowPolicy::Checked;
+    vm.add_process(assemble("ADD 9223372036854775807, 1\nHALT").unwrap())
+        .unwrap();
+    let errors = vm.run();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].kind, ErrorKind::IntegerOverflow);
+}
+
+#[test_log::test]
+fn test_operators() {
+    assert_eq!(run("MOD -7, 3"), Ok(Data::Int(-1)));
+    assert_eq!(run("MOD 7.5, 2"), Ok(Data::Float(1.5)));
+    assert_eq!(run("POW 2, 10"), Ok(Data::Int(1024)));
+    assert_eq!(run("POW 2, -1"), Ok(Data::Float(0.5)));
+    assert_eq!(run("AND 12, 10"), Ok(Data::Int(8)));
+    assert_eq!(run("OR 12u8, 3u8"), Ok(Data::Byte(15)));
+    assert_eq!(run("XOR 6, 3"), Ok(Data::Int(5)));
+    assert_eq!(run("SHL 1, 4"), Ok(Data::Int(16)));
+    assert_eq!(run("SHR -16, 2"), Ok(Data::Int(-4)));
+    assert_eq!(
+        run("GT 3, 1\nAND acc, true\nOR acc, false\nXOR acc, false"),
+        Ok(Data::Bool(true))
+    );
+    assert_eq!(run("NEG 5"), Ok(Data::Int(-5)));
+    assert_eq!(run("NOT false"), Ok(Data::Bool(true)));
+    assert_eq!(run("BITNOT 0u8"), Ok(Data::Byte(255)));
+    assert_eq!(run("ABS -2.5"), Ok(Data::Float(2.5)));
+
+    let checked = |source: &str| {
+        run_with(source, |process| {
+            process.with_overflow(OverflowPolicy::Checked)
+        })
+    };
+    let error = run("MOD 1, 0").unwrap_err();
+    assert_eq!(error.kind, ErrorKind::DivisionByZero);
+    let error = checked("POW 3, 40").unwrap_err();
+    assert_eq!(error.kind, ErrorKind::IntegerOverflow);
+    let error = checked("POW 3, 4294967296").unwrap_err();
+    assert_eq!(error.kind, ErrorKind::IntegerOverflow);
+    assert_eq!(checked("POW -1, 4294967297"), Ok(Data::Int(-1)));
+
+    // Exponents past u32::MAX still wrap and saturate like any other
+    assert_eq!(run("POW 3, 4294967296"), Ok(Data::Int(2491309678558969857)));
+    assert_eq!(run("POW 2, 64"), Ok(Data::Int(0)));
+    let saturating = run_with("POW -2, 4294967297", |process| {
+        process.with_overflow(OverflowPolicy::Saturating)
+    });
+    assert_eq!(saturating, Ok(Data::Int(i64::MIN)));
+    let error = checked("SHL 1u8, 8u8").unwrap_err();
+    assert_eq!(error.kind, ErrorKind::IntegerOverflow);
+    let error = checked("NEG -9223372036854775808").unwrap_err();
+    assert_eq!(error.kind, ErrorKind::IntegerOverflow);
+    let error = run("AND true, 1").unwrap_err();
+    assert_eq!(error.kind, ErrorKind::TypeMismatch);
+    let error = run("NOT 1").unwrap_err();
+    assert_eq!(error.operands, vec![Data::Int(1)]);
+}
+
+#[test_log::test]
+fn test_structural_equality() {
+    let cases = [
+        ("\"abc\"", "\"abc\"", true),
+        ("\"abc\"", "\"abd\"", false),
+        ("true", "true", true),
+        ("true", "1", false),
+        ("none", "none", true),
+        ("none", "0", false),
+        ("none", "false", false),
+        ("x\"0aff\"", "x\"0aff\"", true),
+        ("@3", "@3", true),
+        ("@3", "3", false),
+        ("fn\"main\"", "fn\"main\"", true),
+        ("fn\"main\"", "\"main\"", false),
+        ("nan", "nan", true),
+        ("[1, [2, \"x\"]]", "[1, [2, \"x\"]]", true),
+        ("[1, [2, \"x\"]]", "[1, [2, \"y\"]]", false),
+        ("[1, 2]", "(1, 2)", false),
+        ("(1, (none, true))", "(1, (none, true))", true),
+        ("{\"a\": [1], 2: {3: 4}}", "{2: {3: 4}, \"a\": [1]}", true),
+        ("{\"a\": [1]}", "{\"a\": [2]}", false),
+        ("[]", "{}", false),
+        ("\"1\"", "1", false),
+    ];
+
+    for (a, b, equal) in cases {
+        assert_eq!(
+            run(&format!("EQ {a}, {b}")),
+            Ok(Data::Bool(equal)),
+            "{a} == {b}"
+        );
+        assert_eq!(
+            run(&format!("NEQ {a}, {b}")),
+            Ok(Data::Bool(!equal)),
+            "{a} != {b}"
+        );
+    }
+}
+
+#[test_log::test]
+fn test_type_conversions() {
+    assert_eq!(run("CAST 7, Float"), Ok(Data::Float(7.0)));
+    assert_eq!(run("CAST -2.9, int"), Ok(Data::Int(-2)));
+    assert_eq!(run("CAST \" 42 \", Int"), Ok(Data::Int(42)));
+    assert_eq!(run("CAST \"2.5\", Float"), Ok(Data::Float(2.5)));
+    assert_eq!(run("CAST 1.5, String"), Ok(value("\"1.5\"")));
+    assert_eq!(run("CAST [1, \"a\"], String"), Ok(value(r#""[1, \"a\"]""#)));
+    assert_eq!(run("CAST 300, Byte"), Ok(Data::Byte(44)));
+    assert_eq!(run("CAST 0, Bool"), Ok(Data::Bool(false)));
+    assert_eq!(run("CAST \"hé\", ByteArray"), Ok(value("x\"68c3a9\"")));
+    assert_eq!(run("CAST x\"68c3a9\", String"), Ok(value("\"hé\"")));
+    assert_eq!(
+        run("CAST [(1, \"a\"), [2, \"b\"]], Dict"),
+        Ok(value(r#"{1: "a", 2: "b"}"#))
+    );
+    assert_eq!(run("CAST {1: \"a\"}, List"), Ok(value(r#"[(1, "a")]"#)));
+    assert_eq!(run("TYPEOF {1: 2}"), Ok(value("\"Dict\"")));
+    assert_eq!(run("ISTYPE none, None"), Ok(Data::Bool(true)));
+    assert_eq!(run("ISTYPE 1, Float"), Ok(Data::Bool(false)));
+
+    let checked = |source: &str| {
+        run_with(source, |process| {
+            process.with_overflow(OverflowPolicy::Checked)
+        })
+    };
+    let error = run("CAST \"12a\", Int").unwrap_err();
+    assert_eq!(error.kind, ErrorKind::ParseError);
+    assert_eq!(error.operands, vec![value("\"12a\""), value("\"Int\"")]);
+    let error = checked("CAST 300, Byte").unwrap_err();
+    assert_eq!(error.kind, ErrorKind::IntegerOverflow);
+    let error = checked("CAST nan, Int").unwrap_err();
+    assert_eq!(error.kind, ErrorKind::IntegerOverflow);
+    let error = run("CAST {}, Int").unwrap_err();
+    assert_eq!(error.kind, ErrorKind::TypeMismatch);
+    assert!(assemble("CAST 1, Number\nHALT").is_err());
+}
+
+#[test_log::test]
+fn test_native_functions() {
+    let natives = Rc::new(RefCell::new(Natives::new()));
+    natives
+        .borrow_mut()
+        .register("sum", 2, |args: &[Data]| match args {
+            [Data::Int(a), Data::Int(b)] => Ok(Data::Int(a + b)),
+            _ => Err(ExecError::new(ErrorKind::TypeMismatch, args.to_vec())),
+        });
+    natives.borrow_mut().register("fail", 0, |_: &[Data]| {
+        Err(ErrorKind::NativeFailed("not today".into()).into())
+    });
+    let run = |source: &str| run_with(source, |process| process.with_natives(natives.clone()));
+
+    assert_eq!(
+        run("STORE 2\nSTORE 40\nCALLNATIVE fn\"sum\", 2"),
+        Ok(Data::Int(42))
+    );
+    let error = run("CALLNATIVE fn\"missing\", 0").unwrap_err();
+    assert_eq!(error.kind, ErrorKind::UnknownFunction);
+    assert_eq!(
+        error.operands,
+        vec![Data::Function(Box::new("missing".into()))]
+    );
+    let error = run("STORE 1\nCALLNATIVE fn\"sum\", 1").unwrap_err();
+    assert_eq!(error.kind, ErrorKind::LengthMismatch);
+    let error = run("STORE 1\nSTORE \"a\"\nCALLNATIVE fn\"sum\", 2").unwrap_err();
+    assert_eq!(error.kind, ErrorKind::TypeMismatch);
+    assert_eq!(error.operands, vec![Data::Int(1), value("\"a\"")]);
+    let error = run("CALLNATIVE fn\"fail\", 0").unwrap_err();
+    assert_eq!(error.kind, ErrorKind::NativeFailed("not today".into()));
+
+    // Registered after the process was added, but before it runs
+    let logged = Rc::new(RefCell::new(vec![]));
+    let mut vm = StackMachine::new();
+    vm.add_process(assemble("STORE true\nCALLNATIVE fn\"log\", 1\nHALT").unwrap())
+        .unwrap();
+    let sink = logged.clone();
+    vm.register_native("log", 1, move |args: &[Data]| {
+        sink.borrow_mut().push(args[0].clone());
+        Ok(Data::None)
+    });
+    assert!(vm.run().is_empty());
+    assert_eq!(*logged.borrow(), vec![Data::Bool(true)]);
+
+    let error = vm
+        .add_process(assemble("CALLNATIVE \"sum\", 2\nHALT").unwrap())
+        .unwrap_err();
+    assert!(matches!(error, CompileError::Verification(_)));
+}
+
+#[test_log::test]
+fn test_output_sink() {
+    let shared = Rc::new(RefCell::new(OutputBuffer::new()));
+    let own = Rc::new(RefCell::new(OutputBuffer::new()));
+
+    let mut vm = StackMachine::new();
+    vm.output = shared.clone();
+    vm.add_process(assemble("PRINT 1\nPRINT \"a\"\nHALT").unwrap())
+        .unwrap();
+    vm.add_process(assemble("PRINT 2.5\nHALT").unwrap())
+        .unwrap();
+    vm.add_process_with_output(assemble("PRINT [true]\nHALT").unwrap(), own.clone())
+        .unwrap();
+    vm.add_process(assemble("PRINT none\nHALT").unwrap())
+        .unwrap();
+    assert!(vm.run().is_empty());
+
+    // Every process reports its execution time where it printed
+    let shared = shared.borrow();
+    let (reports, printed): (Vec<_>, Vec<_>) = shared
+        .lines
+        .iter()
+        .partition(|line| line.starts_with("Execution time: "));
+    assert_eq!(printed, ["Int(1)", "String(\"a\")", "Float(2.5)", "None"]);
+    assert_eq!(reports.len(), 3);
+
+    let own = own.borrow();
+    assert_eq!(own.lines.len(), 2);
+    assert_eq!(own.lines[0], "List([Bool(true)])");
+    assert!(own.lines[1].starts_with("Execution time: "));
+    assert!(own.contents().ends_with('\n'));
+}
+
+#[test_log::test]
+fn test_input_source() {
+    // Every process reads on from where the previous one stopped
+    let input: SharedInput = Rc::new(RefCell::new(InputScript::new("hello\r\nwörld\nabcdef")));
+    let read = |source: &str| run_with(source, |process| process.with_input(input.clone()));
+    assert_eq!(read("READLINE"), Ok(value("\"hello\"")));
+    assert_eq!(read("READLINE"), Ok(value("\"wörld\"")));
+    assert_eq!(read("READBYTES 4"), Ok(value("x\"61626364\"")));
+    assert_eq!(read("READBYTES 10"), Ok(value("x\"6566\"")));
+    assert_eq!(read("READBYTES 1"), Ok(Data::None));
+    assert_eq!(read("READLINE"), Ok(Data::None));
+
+    let path = std::env::temp_dir().join(format!("vm_input_{}.txt", std::process::id()));
+    std::fs::write(&path, "from a file\n").unwrap();
+    let file: SharedInput = Rc::new(RefCell::new(InputFile::open(&path).unwrap()));
+    assert_eq!(
+        run_with("READLINE", |process| process.with_input(file)),
+        Ok(value("\"from a file\""))
+    );
+    std::fs::remove_file(&path).unwrap();
+
+    let input: SharedInput = Rc::new(RefCell::new(InputScript::new(vec![0xff, b'\n'])));
+    let error = run_with("READLINE", |process| process.with_input(input)).unwrap_err();
+    assert!(matches!(error.kind, ErrorKind::InputFailed(_)));
+    let error = run("READBYTES -1").unwrap_err();
+    assert_eq!(error.kind, ErrorKind::TypeMismatch);
+}
+
+#[test_log::test]
+fn test_debugger() {
+    let source = "
+                STORE 20
+                CALL double
+                ADD acc, 2
+                HALT
+        double: MUL $0, 2
+                RETURN
+    ";
+    let process = |source: &str| {
+        let bytecode = assemble(source).unwrap().compile().unwrap();
+        let heap = Rc::new(RefCell::new(Heap::new()));
+        Process::new(StackSize::default(), heap, bytecode)
+    };
+
+    let mut debugged = process(source);
+    debugged.set_breakpoint(4);
+    assert_eq!(debugged.breakpoints().len(), 1);
+    assert_eq!(debugged.resume(), Stop::Breakpoint(4));
+    let context = debugged.context();
+    assert_eq!(context.get_ipntr(), 4);
+    assert_eq!(context.calls_history().len(), 1);
+    assert_eq!(context.calls_history()[0].return_ip, 1);
+    assert_eq!(context.stack.peek_register(1), Ok(&Data::Int(20)));
+
+    assert_eq!(
+        debugged.current(),
+        Some(&Instruction::BinaryOp(
+            BinaryOp::Multiply,
+            Arg::Ref(0),
+            Arg::Const(Data::Int(2))
+        ))
+    );
+    assert_eq!(debugged.step(), Stop::Step);
+    assert_eq!(*debugged.context().stack.accumulator(), Data::Int(40));
+    assert_eq!(debugged.step(), Stop::Step);
+    assert_eq!(debugged.context().get_ipntr(), 2);
+    assert!(debugged.context().calls_history().is_empty());
+    assert_eq!(debugged.resume(), Stop::Finished);
+    assert_eq!(*debugged.context().stack.accumulator(), Data::Int(42));
+    assert_eq!(debugged.step(), Stop::Finished);
+
+    // Stepping over the call runs the whole subroutine, unless it has a breakpoint
+    let mut debugged = process(source);
+    debugged.step();
+    assert_eq!(debugged.step_over(), Stop::Step);
+    assert_eq!(debugged.context().get_ipntr(), 2);
+    assert_eq!(*debugged.context().stack.accumulator(), Data::Int(40));
+
+    let mut debugged = process(source);
+    debugged.set_breakpoint(5);
+    debugged.step();
+    assert_eq!(debugged.step_over(), Stop::Breakpoint(5));
+    assert!(debugged.clear_breakpoint(5));
+    assert!(!debugged.clear_breakpoint(5));
+    assert_eq!(debugged.resume(), Stop::Finished);
+
+    let mut crashed = process("DIV 1, 0\nHALT");
+    let Stop::Crashed(error) = crashed.step() else {
+        panic!("division by zero must crash");
+    };
+    assert_eq!(error.kind, ErrorKind::DivisionByZero);
+
+    // Input that never arrives hands control back instead of spinning
+    struct Silent;
+    impl Input for Silent {
+        fn read_line(&mut self) -> io::Result<Option<String>> {
+            Err(io::ErrorKind::WouldBlock.into())
+        }
+
+        fn read_bytes(&mut self, _count: usize) -> io::Result<Option<Vec<u8>>> {
+            Err(io::ErrorKind::WouldBlock.into())
+        }
+    }
+    let mut waiting = process("STORE 1\nREADLINE\nHALT").with_input(Rc::new(RefCell::new(Silent)));
+    assert_eq!(waiting.resume(), Stop::Blocked);
+    assert_eq!(waiting.context().get_ipntr(), 1);
+    assert_eq!(waiting.step_over(), Stop::Blocked);
+    assert_eq!(waiting.step(), Stop::Blocked);
+    assert_eq!(waiting.context().get_ipntr(), 1);
+
+    // Processes scheduled by the machine are reached through their pid
+    let mut vm = StackMachine::new();
+    vm.output = Rc::new(RefCell::new(OutputBuffer::new()));
+    let pid = vm.add_process(assemble(source).unwrap()).unwrap();
+    let scheduled = vm.process_mut::<Instruction>(pid).unwrap();
+    scheduled.set_breakpoint(4);
+    assert_eq!(scheduled.resume(), Stop::Breakpoint(4));
+    assert_eq!(scheduled.step(), Stop::Step);
+    assert_eq!(*scheduled.context().stack.accumulator(), Data::Int(40));
+    assert!(vm.process_mut::<Instruction>(pid.wrapping_add(1)).is_none());
+    assert!(vm.run().is_empty());
+    assert!(vm.process_mut::<Instruction>(pid).is_none());
+}
+
+// ------------------------
+// MARK: HELPERS
+//------------------------
+
+/// Runs a snippet followed by `HALT` in a process of its own, returning what it left in
+/// the accumulator or the error that crashed it.
+fn run(source: &str) -> Result<Data, VmError<Data>> {
+    run_with(source, |process| process)
+}
+
+/// Like `run`, letting `setup` configure the process first.
+fn run_with(
+    source: &str,
+    setup: impl FnOnce(Process<Instruction, Data>) -> Process<Instruction, Data>,
+) -> Result<Data, VmError<Data>> {
+    let bytecode = assemble(&format!("{source}\nHALT"))
+        .unwrap()
+        .compile()
+        .unwrap();
+    bytecode.verify().unwrap();
+    let heap = Rc::new(RefCell::new(Heap::new()));
+    let mut process = setup(Process::new(StackSize::default(), heap, bytecode));
+    match process.resume() {
+        Stop::Finished => Ok(process.context().stack.accumulator().clone()),
+        Stop::Crashed(error) => Err(error),
+        stop => panic!("unexpected stop {stop:?}"),
+    }
+}
+
+/// Parses a value written as an assembler constant.
+fn value(text: &str) -> Data {
+    let bytecode = assemble(&format!(".const {text}\nHALT"))
+        .unwrap()
+        .compile()
+        .unwrap();
+    bytecode.get_constants()[0].clone()
 }
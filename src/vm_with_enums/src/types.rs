@@ -0,0 +1,250 @@
+use std::collections::BTreeMap;
+
+use vm_lib::{ErrorKind, ExecError, OverflowPolicy, ProcessContext};
+
+use crate::{
+    data_types::{Arg, Data},
+    instructions::{BinaryOp, Instruction},
+};
+
+type OpProc = ProcessContext<Data>;
+type OpResult<T = ()> = Result<T, ExecError<Data>>;
+
+// ------------------------
+// MARK: TYPES
+//------------------------
+
+/// Variant of a `Data` value, written by name in `CAST`, `TYPEOF` and `ISTYPE`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataType {
+    Int,
+    Float,
+    Bool,
+    Byte,
+    ByteArray,
+    String,
+    Tuple,
+    List,
+    Dict,
+    Pointer,
+    Function,
+    None,
+}
+
+// ------------------------
+// MARK: IMPLEMENTS
+//------------------------
+
+impl DataType {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            DataType::Int => "Int",
+            DataType::Float => "Float",
+            DataType::Bool => "Bool",
+            DataType::Byte => "Byte",
+            DataType::ByteArray => "ByteArray",
+            DataType::String => "String",
+            DataType::Tuple => "Tuple",
+            DataType::List => "List",
+            DataType::Dict => "Dict",
+            DataType::Pointer => "Pointer",
+            DataType::Function => "Function",
+            DataType::None => "None",
+        }
+    }
+
+    /// Case insensitive, like mnemonics.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let kind = match name.to_ascii_lowercase().as_str() {
+            "int" => DataType::Int,
+            "float" => DataType::Float,
+            "bool" => DataType::Bool,
+            "byte" => DataType::Byte,
+            "bytearray" => DataType::ByteArray,
+            "string" => DataType::String,
+            "tuple" => DataType::Tuple,
+            "list" => DataType::List,
+            "dict" => DataType::Dict,
+            "pointer" => DataType::Pointer,
+            "function" => DataType::Function,
+            "none" => DataType::None,
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    pub const fn of(value: &Data) -> Self {
+        match value {
+            Data::Int(_) => DataType::Int,
+            Data::Float(_) => DataType::Float,
+            Data::Bool(_) => DataType::Bool,
+            Data::Byte(_) => DataType::Byte,
+            Data::ByteArray(_) => DataType::ByteArray,
+            Data::String(_) => DataType::String,
+            Data::Tuple(_) => DataType::Tuple,
+            Data::List(_) => DataType::List,
+            Data::Dict(_) => DataType::Dict,
+            Data::Pointer(_) => DataType::Pointer,
+            Data::Function(_) => DataType::Function,
+            Data::None => DataType::None,
+        }
+    }
+}
+
+impl Instruction {
+    pub(crate) fn cast(proc: &mut OpProc, arg: &Arg, target: DataType) -> OpResult {
+        let policy = proc.overflow();
+        let value = arg.deref(proc)?;
+
+        let result = Self::convert(value, target, policy)?;
+        proc.stack.to_register(result);
+        Ok(())
+    }
+
+    pub(crate) fn type_of(proc: &mut OpProc, arg: &Arg) -> OpResult {
+        let name = DataType::of(arg.deref(proc)?).name();
+
+        proc.stack
+            .to_register(Data::String(Box::new(name.to_string())));
+        Ok(())
+    }
+
+    pub(crate) fn is_type(proc: &mut OpProc, arg: &Arg, expected: DataType) -> OpResult {
+        let found = DataType::of(arg.deref(proc)?) == expected;
+
+        proc.stack.to_register(Data::Bool(found));
+        Ok(())
+    }
+
+    /// Numbers that do not fit the target follow the overflow policy, except that floats
+    /// saturate when it is not `Checked`. Strings are parsed, ignoring surrounding spaces.
+    fn convert(value: &Data, target: DataType, policy: OverflowPolicy) -> OpResult<Data> {
+        if DataType::of(value) == target {
+            return Ok(value.clone());
+        }
+        let operands = [value, &Data::String(Box::new(target.name().to_string()))];
+
+        let result = match (target, value) {
+            (DataType::Int, Data::Float(x)) => {
+                let fits = x.trunc() >= i64::MIN as f64 && x.trunc() < i64::MAX as f64;
+                let checked = fits.then_some(*x as i64);
+                Data::Int(BinaryOp::integer(
+                    policy, checked, *x as i64, *x as i64, &operands,
+                )?)
+            }
+            (DataType::Int, Data::Bool(x)) => Data::Int(*x as i64),
+            (DataType::Int, Data::Byte(x)) => Data::Int(*x as i64),
+            (DataType::Int, Data::String(text)) => Data::Int(Self::parse(text, &operands)?),
+            (DataType::Int, Data::Pointer(x)) => Data::Int(BinaryOp::integer(
+                policy,
+                i64::try_from(*x).ok(),
+                *x as i64,
+                i64::try_from(*x).unwrap_or(i64::MAX),
+                &operands,
+            )?),
+            (DataType::Float, Data::Int(x)) => Data::Float(*x as f64),
+            (DataType::Float, Data::Bool(x)) => Data::Float(*x as u8 as f64),
+            (DataType::Float, Data::Byte(x)) => Data::Float(*x as f64),
+            (DataType::Float, Data::String(text)) => Data::Float(Self::parse(text, &operands)?),
+            (DataType::Bool, Data::Int(x)) => Data::Bool(*x != 0),
+            (DataType::Bool, Data::Float(x)) => Data::Bool(*x != 0.0),
+            (DataType::Bool, Data::Byte(x)) => Data::Bool(*x != 0),
+            (DataType::Bool, Data::String(text)) => Data::Bool(Self::parse(text, &operands)?),
+            (DataType::Bool, Data::None) => Data::Bool(false),
+            (DataType::Byte, Data::Int(x)) => Data::Byte(BinaryOp::integer(
+                policy,
+                u8::try_from(*x).ok(),
+                *x as u8,
+                (*x).clamp(0, u8::MAX as i64) as u8,
+                &operands,
+            )?),
+            (DataType::Byte, Data::Float(x)) => {
+                let fits = x.trunc() >= 0.0 && x.trunc() <= u8::MAX as f64;
+                let checked = fits.then_some(*x as u8);
+                Data::Byte(BinaryOp::integer(
+                    policy, checked, *x as u8, *x as u8, &operands,
+                )?)
+            }
+            (DataType::Byte, Data::Bool(x)) => Data::Byte(*x as u8),
+            (DataType::Byte, Data::String(text)) => Data::Byte(Self::parse(text, &operands)?),
+            (DataType::ByteArray, Data::String(text)) => {
+                Data::ByteArray(Box::new(text.as_bytes().into()))
+            }
+            (DataType::ByteArray, Data::List(items)) => Self::bytes(items, &operands)?,
+            (DataType::ByteArray, Data::Tuple(items)) => Self::bytes(items, &operands)?,
+            (DataType::String, Data::ByteArray(bytes)) => match str::from_utf8(bytes) {
+                Ok(text) => Data::String(Box::new(text.to_string())),
+                Err(_) => return Err(Self::unparsable(&operands)),
+            },
+            (DataType::String, Data::Byte(x)) => Data::String(Box::new(x.to_string())),
+            (DataType::String, Data::Function(name)) => Data::String(name.clone()),
+            (DataType::String, value) => Data::String(Box::new(value.to_string())),
+            (DataType::Tuple, Data::List(items)) => {
+                Data::Tuple(Box::new(items.clone().into_boxed_slice()))
+            }
+            (DataType::List, Data::Tuple(items)) => Data::List(Box::new(items.to_vec())),
+            (DataType::List, Data::ByteArray(bytes)) => Data::List(Box::new(
+                bytes.iter().map(|byte| Data::Byte(*byte)).collect(),
+            )),
+            // Entries become (key, value) tuples
+            (DataType::List, Data::Dict(entries)) => Data::List(Box::new(
+                entries
+                    .iter()
+                    .map(|(key, value)| Data::Tuple(Box::new([key.clone(), value.clone()].into())))
+                    .collect(),
+            )),
+            (DataType::Dict, Data::List(items)) => Self::entries(items, &operands)?,
+            (DataType::Dict, Data::Tuple(items)) => Self::entries(items, &operands)?,
+            (DataType::Pointer, Data::Int(x)) => match u64::try_from(*x) {
+                Ok(pointer) => Data::Pointer(pointer),
+                Err(_) => return Err(Self::unconvertible(&operands)),
+            },
+            (DataType::Function, Data::String(name)) => Data::Function(name.clone()),
+            _ => return Err(Self::unconvertible(&operands)),
+        };
+
+        Ok(result)
+    }
+
+    fn parse<T: std::str::FromStr>(text: &str, operands: &[&Data]) -> OpResult<T> {
+        text.trim().parse().map_err(|_| Self::unparsable(operands))
+    }
+
+    fn bytes(items: &[Data], operands: &[&Data]) -> OpResult<Data> {
+        let bytes = items
+            .iter()
+            .map(|item| match item {
+                Data::Byte(byte) => Ok(*byte),
+                _ => Err(Self::unconvertible(operands)),
+            })
+            .collect::<OpResult<Vec<_>>>()?;
+        Ok(Data::ByteArray(Box::new(bytes.into_boxed_slice())))
+    }
+
+    /// Builds a Dict out of (key, value) pairs, each one a Tuple or List.
+    fn entries(items: &[Data], operands: &[&Data]) -> OpResult<Data> {
+        let mut entries = BTreeMap::new();
+        for item in items {
+            let pair = match item {
+                Data::Tuple(pair) => pair.as_ref(),
+                Data::List(pair) => pair.as_slice(),
+                _ => return Err(Self::unconvertible(operands)),
+            };
+            let [key, value] = pair else {
+                return Err(Self::unconvertible(operands));
+            };
+            entries.insert(key.clone(), value.clone());
+        }
+        Ok(Data::Dict(Box::new(entries)))
+    }
+
+    fn unparsable(operands: &[&Data]) -> ExecError<Data> {
+        let operands = operands.iter().map(|value| (*value).clone()).collect();
+        ExecError::new(ErrorKind::ParseError, operands)
+    }
+
+    fn unconvertible(operands: &[&Data]) -> ExecError<Data> {
+        let operands = operands.iter().map(|value| (*value).clone()).collect();
+        ExecError::new(ErrorKind::TypeMismatch, operands)
+    }
+}